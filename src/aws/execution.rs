@@ -0,0 +1,197 @@
+use crate::{
+    error::{Error, Result},
+    journal::{Journal, State},
+    options::Kind,
+    out::Entry,
+};
+use aws_sdk_ec2::{
+    model::{Filter, ImageState},
+    Client,
+};
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::{collections::VecDeque, time::Duration};
+
+/// Seconds waited between checks that a deregistration is visible, before giving up.
+const VISIBILITY: [u64; 6] = [1, 2, 4, 8, 16, 32];
+
+/// How a resource of the plan ended.
+pub enum Outcome {
+    Deleted,
+    Failed(Box<Error>),
+    /// Left alone, as the named parent was not deleted.
+    Skipped(String),
+}
+
+pub struct Record {
+    pub kind: Kind,
+    pub id: String,
+    pub outcome: Outcome,
+}
+
+/// Resource of the plan, deleted once its parent is.
+struct Node {
+    kind: Kind,
+    id: String,
+    children: Vec<usize>,
+}
+
+/// Appends the entries and their dependents, protected ones and theirs excluded, returning the roots.
+fn flatten(entries: Vec<Entry>, nodes: &mut Vec<Node>) -> Vec<usize> {
+    entries
+        .into_iter()
+        .filter(|entry| !entry.protected)
+        .map(|entry| {
+            let children = flatten(entry.children, nodes);
+            nodes.push(Node {
+                kind: entry.kind,
+                id: entry.id,
+                children,
+            });
+            nodes.len() - 1
+        })
+        .collect()
+}
+
+/// Deletes each phase in turn, every resource once its parent is deleted, `parallelism` at a time.
+///
+/// Dependents of a resource that failed are skipped, resources the journal knows deleted too.
+/// Resources already gone count as deleted.
+pub async fn execute(
+    client: &Client,
+    phases: Vec<Vec<Entry>>,
+    parallelism: usize,
+    journal: &mut Journal,
+) -> Vec<Record> {
+    let mut records = vec![];
+
+    for entries in phases {
+        let mut nodes = vec![];
+        let mut ready = VecDeque::from(flatten(entries, &mut nodes));
+        let mut outcomes = nodes.iter().map(|_| None).collect::<Vec<_>>();
+
+        {
+            let mut running = FuturesUnordered::new();
+            loop {
+                while running.len() < parallelism.max(1) {
+                    let Some(index) = ready.pop_front() else {
+                        break;
+                    };
+                    let node: &Node = &nodes[index];
+                    if journal.is_done(&node.id) {
+                        log::info!("{} {} was deleted by a previous run", node.kind, node.id);
+                        outcomes[index] = Some(Outcome::Deleted);
+                        ready.extend(&node.children);
+                        continue;
+                    }
+                    journal.record(node.kind, &node.id, State::Started, None);
+                    running.push(async move { (index, delete(client, node.kind, &node.id).await) });
+                }
+
+                let Some((index, result)) = running.next().await else {
+                    break;
+                };
+                let node = &nodes[index];
+                outcomes[index] = Some(match result {
+                    Err(error) if !error.is_not_found() => {
+                        journal.record(node.kind, &node.id, State::Failed, Some(error.report()));
+                        skip(&nodes, index, &mut outcomes, journal);
+                        Outcome::Failed(Box::new(error))
+                    }
+                    result => {
+                        if result.is_err() {
+                            log::info!("{} {} was already deleted", node.kind, node.id);
+                        } else {
+                            log::info!("Deleted {} {}", node.kind, node.id);
+                        }
+                        journal.record(node.kind, &node.id, State::Deleted, None);
+                        ready.extend(&node.children);
+                        Outcome::Deleted
+                    }
+                });
+            }
+        }
+
+        records.extend(
+            std::iter::zip(nodes, outcomes).filter_map(|(node, outcome)| {
+                Some(Record {
+                    kind: node.kind,
+                    id: node.id,
+                    outcome: outcome?,
+                })
+            }),
+        );
+    }
+
+    records
+}
+
+/// Skips the entries and their dependents, protected ones excluded, as the parent they wait for in an
+/// earlier phase was not deleted.
+pub fn hold(entries: Vec<Entry>, parent: &str, journal: &mut Journal) -> Vec<Record> {
+    let mut nodes = vec![];
+    flatten(entries, &mut nodes);
+    nodes
+        .into_iter()
+        .map(|node| {
+            journal.record(node.kind, &node.id, State::Skipped, Some(parent.to_owned()));
+            Record {
+                kind: node.kind,
+                id: node.id,
+                outcome: Outcome::Skipped(parent.to_owned()),
+            }
+        })
+        .collect()
+}
+
+/// Marks every dependent of the node as skipped.
+fn skip(nodes: &[Node], index: usize, outcomes: &mut [Option<Outcome>], journal: &mut Journal) {
+    let parent = &nodes[index];
+    for &child in &parent.children {
+        let node = &nodes[child];
+        journal.record(node.kind, &node.id, State::Skipped, Some(parent.id.clone()));
+        outcomes[child] = Some(Outcome::Skipped(parent.id.clone()));
+        skip(nodes, child, outcomes, journal);
+    }
+}
+
+async fn delete(client: &Client, kind: Kind, id: &str) -> Result<()> {
+    match kind {
+        Kind::Image => {
+            client.deregister_image().image_id(id).send().await?;
+            deregistered(client, id).await
+        }
+        Kind::Snapshot => {
+            client.delete_snapshot().snapshot_id(id).send().await?;
+            Ok(())
+        }
+        Kind::Volume => {
+            client.delete_volume().volume_id(id).send().await?;
+            Ok(())
+        }
+    }
+}
+
+/// Waits until EC2 no longer lists the image as available, so its snapshots can be deleted.
+async fn deregistered(client: &Client, id: &str) -> Result<()> {
+    for delay in VISIBILITY {
+        let output = client
+            .describe_images()
+            .owners("self")
+            .filters(Filter::builder().name("image-id").values(id).build())
+            .send()
+            .await?;
+        if output
+            .images()
+            .unwrap_or_default()
+            .iter()
+            .all(|image| image.state() == Some(&ImageState::Deregistered))
+        {
+            return Ok(());
+        }
+
+        log::debug!("Waiting {delay}s for the deregistration of {id} to be visible");
+        tokio::time::sleep(Duration::from_secs(delay)).await;
+    }
+
+    Err(Error::StillRegistered(id.to_owned()))
+}
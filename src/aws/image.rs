@@ -1,22 +1,52 @@
 use crate::{
-    aws::snapshot::{Builder as SnapshotsBuilder, DescribeSnapshots, Snapshots},
-    error::Result,
+    aws::{
+        permission::Action,
+        preflight::Samples,
+        snapshot::{Builder as SnapshotsBuilder, DescribeSnapshots, Snapshots},
+        tags,
+    },
+    error::{regex, regex_set, report, skip, Error, Result, UNKNOWN_ID},
+    filter::{age, Resource, Selection, Value},
+    guard::Inventory,
+    options::{Cascade, Kind, NeverLaunched, VersionOrder},
+    out::{details, Entry},
+    retention::{Rule, Schedule},
 };
 use aws_sdk_ec2::{
-    model::{Filter, Image},
+    model::{Filter, Image, ImageAttributeName},
     output::DescribeImagesOutput,
     Client,
 };
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, SecondsFormat, Utc};
 use futures::future::join_all;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+};
+
+/// Actions needed to plan image cleanups, backing snapshots lookup included.
+pub const READ: &[Action] = &[
+    Action::DescribeImageAttribute,
+    Action::DescribeImages,
+    Action::DescribeInstances,
+    Action::DescribeSnapshots,
+    Action::DescribeVolumes,
+];
+
+/// Actions needed to apply image cleanups, backing snapshots deletion included.
+pub const APPLY: &[Action] = &[
+    Action::DeregisterImage,
+    Action::DeleteSnapshot,
+    Action::DeleteVolume,
+];
 
 #[derive(Default)]
 pub struct DescribeImages {
     pub names: Option<Vec<String>>,
     pub tags: Option<Vec<String>>,
     pub snapshot_id: Option<String>,
+    pub filters: Vec<Filter>,
 }
 
 struct Filters(Option<Vec<Filter>>);
@@ -38,9 +68,9 @@ impl From<DescribeImages> for Filters {
             let mut key_values = HashMap::new();
 
             for tag in tags {
-                let mut splits = tag.split(" ");
+                let mut splits = tag.split(' ');
                 key_values
-                    .entry(splits.next().expect("Failed to read tag's key").to_string())
+                    .entry(splits.next().unwrap_or_default().to_string())
                     .or_insert(vec![])
                     .append(&mut splits.map(|split| split.to_string()).collect::<Vec<_>>());
             }
@@ -55,6 +85,8 @@ impl From<DescribeImages> for Filters {
             }
         }
 
+        filters.extend(describe_images.filters);
+
         if let Some(id) = describe_images.snapshot_id {
             filters.push(
                 Filter::builder()
@@ -75,6 +107,8 @@ impl From<DescribeImages> for Filters {
 pub struct Builder<'a> {
     client: &'a Client,
     describe_images_output: DescribeImagesOutput,
+    /// Last launch of the images launched, only looked up when retaining by it.
+    launches: HashMap<String, Option<DateTime<Utc>>>,
 }
 
 impl<'a> Builder<'a> {
@@ -89,36 +123,61 @@ impl<'a> Builder<'a> {
         let builder = Self {
             client,
             describe_images_output,
+            launches: HashMap::new(),
         }
+        .validate()
         .sort();
 
         if let Some(images) = builder.describe_images_output.images() {
             log::info!("Found {} matching images", images.len());
         }
 
-        let builder = builder.unused().await?;
+        Ok(builder)
+    }
 
-        if let Some(images) = builder.describe_images_output.images() {
-            log::info!("{} of them are unused", images.len());
+    /// Number of resources still candidates.
+    pub fn candidates(&self) -> usize {
+        self.describe_images_output.images().map_or(0, <[_]>::len)
+    }
+
+    /// Whether the image is still a candidate.
+    pub fn contains(&self, id: &str) -> bool {
+        self.describe_images_output
+            .images()
+            .unwrap_or_default()
+            .iter()
+            .any(|image| image.image_id() == Some(id))
+    }
+
+    pub fn filter(self, selection: &Selection) -> Self {
+        let describe_images_output = DescribeImagesOutput::builder()
+            .set_images(self.describe_images_output.images.map(|images| {
+                images
+                    .into_iter()
+                    .filter(|image| selection.matches(image))
+                    .collect::<Vec<_>>()
+            }))
+            .build();
+
+        if let Some(images) = describe_images_output.images() {
+            log::info!("Kept {} after filtering", images.len());
         }
 
-        Ok(builder)
+        Self {
+            client: self.client,
+            describe_images_output,
+            launches: self.launches,
+        }
     }
 
-    pub fn exclude_names(self, names: Vec<String>) -> Self {
-        let regex = regex::RegexSet::new(names).expect("Failed to build regex");
+    pub fn exclude_names(self, names: Vec<String>) -> Result<Self> {
+        let regex = regex_set(&names)?;
 
         let describe_images_output = DescribeImagesOutput::builder()
             .set_images(self.describe_images_output.images.map(|images| {
                 images
                     .into_iter()
-                    .filter(|image| {
-                        regex
-                            .matches(image.name().expect("Failed to read image name"))
-                            .into_iter()
-                            .collect::<Vec<_>>()
-                            .is_empty()
-                    })
+                    .filter(|image| !regex.is_match(image.name().unwrap_or_default()))
                     .collect::<Vec<_>>()
             }))
             .build();
@@ -127,32 +186,28 @@ impl<'a> Builder<'a> {
             log::info!("Kept {} after excluding by name", images.len());
         }
 
-        Self {
+        Ok(Self {
             client: self.client,
             describe_images_output,
-        }
+            launches: self.launches,
+        })
     }
 
-    pub fn exclude_tags(self, tags: Vec<String>) -> Self {
+    pub fn exclude_tags(self, tags: Vec<String>) -> Result<Self> {
         let mut key_values = HashMap::new();
 
         for tag in tags {
-            let mut splits = tag.split(" ");
+            let mut splits = tag.split(' ');
             key_values
-                .entry(splits.next().expect("Failed to read tag's key").to_string())
+                .entry(splits.next().unwrap_or_default().to_string())
                 .or_insert(vec![])
                 .append(&mut splits.map(|split| split.to_string()).collect::<Vec<_>>());
         }
 
         let key_values = key_values
             .into_iter()
-            .map(|(key, values)| {
-                (
-                    regex::Regex::new(&key).expect("Faild to compile regex"),
-                    regex::RegexSet::new(values).expect("Failed to compile regex set"),
-                )
-            })
-            .collect::<Vec<_>>();
+            .map(|(key, values)| Ok((regex(&key)?, regex_set(&values)?)))
+            .collect::<Result<Vec<_>>>()?;
 
         let describe_images_output = DescribeImagesOutput::builder()
             .set_images(self.describe_images_output.images.map(|images| {
@@ -184,19 +239,28 @@ impl<'a> Builder<'a> {
             log::info!("Kept {} after excluding by tag", images.len());
         }
 
-        Self {
+        Ok(Self {
             client: self.client,
             describe_images_output,
-        }
+            launches: self.launches,
+        })
     }
 
-    pub fn keep(self, keep: usize) -> Self {
+    /// Drops the `keep` newest images of each group.
+    pub fn keep(self, keep: usize, grouping: &Grouping) -> Self {
+        let mut kept = HashMap::new();
         let output = DescribeImagesOutput::builder()
-            .set_images(
-                self.describe_images_output
-                    .images
-                    .map(|images| images.into_iter().skip(keep).collect::<Vec<_>>()),
-            )
+            .set_images(self.describe_images_output.images.map(|images| {
+                // Images are sorted newest first.
+                images
+                    .into_iter()
+                    .filter(|image| {
+                        let count = kept.entry(grouping.image(image)).or_insert(0);
+                        *count += 1;
+                        *count > keep
+                    })
+                    .collect::<Vec<_>>()
+            }))
             .build();
 
         if let Some(images) = output.images() {
@@ -206,6 +270,7 @@ impl<'a> Builder<'a> {
         Self {
             client: self.client,
             describe_images_output: output,
+            launches: self.launches,
         }
     }
 
@@ -214,14 +279,7 @@ impl<'a> Builder<'a> {
             .set_images(self.describe_images_output.images.map(|images| {
                 images
                     .into_iter()
-                    .filter(|image| {
-                        image
-                            .creation_date()
-                            .unwrap()
-                            .parse::<DateTime<Utc>>()
-                            .unwrap()
-                            < before
-                    })
+                    .filter(|image| creation_date(image).is_ok_and(|date| date < before))
                     .collect::<Vec<_>>()
             }))
             .build();
@@ -233,15 +291,128 @@ impl<'a> Builder<'a> {
         Self {
             client: self.client,
             describe_images_output: output,
+            launches: self.launches,
         }
     }
 
+    /// Drops the images of each group kept by the schedule, returning them with their rule.
+    pub fn gfs(
+        self,
+        schedule: &Schedule,
+        grouping: &Grouping,
+    ) -> Result<(Self, BTreeMap<String, Rule>)> {
+        let mut retained = BTreeMap::new();
+
+        let images = if let Some(images) = self.describe_images_output.images {
+            let mut groups = HashMap::<_, Vec<_>>::new();
+            for image in images {
+                groups
+                    .entry(grouping.image(&image))
+                    .or_default()
+                    .push(image);
+            }
+
+            let mut candidates = vec![];
+            for images in groups.into_values() {
+                let dates = images
+                    .iter()
+                    .map(creation_date)
+                    .collect::<Result<Vec<_>>>()?;
+                for (image, rule) in std::iter::zip(images, schedule.retain(&dates)) {
+                    match rule {
+                        Some(rule) => {
+                            log::info!("Keeping {} as {rule}", image_id(&image));
+                            retained.insert(image_id(&image), rule);
+                        }
+                        None => candidates.push(image),
+                    }
+                }
+            }
+            candidates.sort_by_key(|image| Reverse(creation_date(image).ok()));
+            Some(candidates)
+        } else {
+            None
+        };
+
+        let output = DescribeImagesOutput::builder().set_images(images).build();
+
+        if let Some(images) = output.images() {
+            log::info!("Will delete {} images and associated data", images.len());
+        }
+
+        Ok((
+            Self {
+                client: self.client,
+                describe_images_output: output,
+                launches: self.launches,
+            },
+            retained,
+        ))
+    }
+
+    /// Keeps images last launched before the date, judging those never launched as asked.
+    ///
+    /// Images whose last launch cannot be looked up are reported and left out of the plan.
+    pub async fn unlaunched(
+        self,
+        before: DateTime<Utc>,
+        never_launched: NeverLaunched,
+    ) -> Result<Self> {
+        let mut launches = self.launches;
+        let images = if let Some(images) = self.describe_images_output.images {
+            let lookups = join_all(
+                images
+                    .iter()
+                    .map(|image| last_launched_time(self.client, image_id(image))),
+            )
+            .await;
+
+            let mut candidates = vec![];
+            for (image, lookup) in std::iter::zip(images, lookups) {
+                let launch = match lookup {
+                    Ok(launch) => launch,
+                    Err(error) => {
+                        report(&error);
+                        continue;
+                    }
+                };
+                launches.insert(image_id(&image), launch);
+                let expired = match launch {
+                    Some(date) => date < before,
+                    None => match never_launched {
+                        NeverLaunched::Creation => creation_date(&image)? < before,
+                        NeverLaunched::Keep => false,
+                        NeverLaunched::Delete => true,
+                    },
+                };
+                if expired {
+                    candidates.push(image);
+                }
+            }
+            Some(candidates)
+        } else {
+            None
+        };
+
+        let output = DescribeImagesOutput::builder().set_images(images).build();
+
+        if let Some(images) = output.images() {
+            log::info!("{} images were not launched since {before}", images.len());
+        }
+
+        Ok(Self {
+            client: self.client,
+            describe_images_output: output,
+            launches,
+        })
+    }
+
     async fn is_image_used(client: &'a Client, image: &Image) -> Result<bool> {
         if let Some(reservations) = client
             .describe_instances()
             .set_filters(Some(vec![Filter::builder()
                 .set_name(Some(("image-id").to_owned()))
-                .set_values(Some(vec![image.image_id().unwrap().to_string()]))
+                .set_values(Some(vec![image.image_id().unwrap_or_default().to_string()]))
                 .build()]))
             .send()
             .await?
@@ -253,7 +424,8 @@ impl<'a> Builder<'a> {
         }
     }
 
-    async fn unused(self) -> Result<Builder<'a>> {
+    /// Drops images instances were launched from, or whose usage cannot be checked.
+    pub async fn unused(self) -> Result<Builder<'a>> {
         if let Some(images) = self.describe_images_output.images {
             let status = join_all(
                 images
@@ -262,52 +434,88 @@ impl<'a> Builder<'a> {
             )
             .await;
 
-            // TODO: this might be enhanced using filter_map.
-            let image_status = std::iter::zip(images, status);
-
-            let filtered = image_status
-                .into_iter()
-                .filter(|(_, status)| !*status.as_ref().unwrap())
+            // Images whose usage cannot be checked are considered used.
+            let images = std::iter::zip(images, status)
+                .filter_map(|(image, status)| match status {
+                    Ok(false) => Some(image),
+                    Ok(true) => None,
+                    Err(error) => {
+                        report(&error);
+                        None
+                    }
+                })
                 .collect::<Vec<_>>();
 
-            let images = filtered
-                .into_iter()
-                .map(|(image, _)| image)
-                .collect::<Vec<_>>();
+            log::info!("{} of them are unused", images.len());
 
             Ok(Self {
                 client: self.client,
                 describe_images_output: DescribeImagesOutput::builder()
                     .set_images(Some(images))
                     .build(),
+                launches: self.launches,
             })
         } else {
             Ok(self)
         }
     }
 
+    /// Drops images lacking the attributes the pipeline relies on.
+    fn validate(mut self) -> Self {
+        if let Some(images) = self.describe_images_output.images.take() {
+            self.describe_images_output.images = Some(skip(
+                images
+                    .into_iter()
+                    .map(|image| validate(&image).map(|_| image)),
+            ));
+        }
+
+        self
+    }
+
+    /// Sorts images by version, highest first, then by creation date.
+    ///
+    /// Images lacking a version are ordered among the others by creation date.
+    pub fn sort_by_version(mut self, versioning: &Versioning) -> Self {
+        if let Some(images) = self.describe_images_output.images.take() {
+            self.describe_images_output.images = Some(by_version(
+                images,
+                |image| versioning.version(image),
+                |image| creation_date(image).ok(),
+            ));
+        }
+
+        self
+    }
+
     fn sort(mut self) -> Self {
         if let Some(images) = &mut self.describe_images_output.images {
-            images.sort_by(|lhs, rhs| {
-                lhs.creation_date()
-                    .map(|d| d.parse::<DateTime<Utc>>().unwrap())
-                    .unwrap()
-                    .cmp(
-                        &rhs.creation_date()
-                            .map(|d| d.parse::<DateTime<Utc>>().unwrap())
-                            .unwrap(),
-                    )
-                    .reverse()
-            });
+            images.sort_by_key(|image| Reverse(creation_date(image).ok()));
         }
 
         self
     }
 
-    pub async fn build(self) -> Result<Images> {
+    /// Looks up the last launch of the images `unlaunched` did not, leaving it out when that fails.
+    pub async fn build(self, grouping: &Grouping, cascade: Cascade) -> Result<Images> {
+        let (client, launches) = (self.client, &self.launches);
         Ok(Images(
             if let Some(images) = self.describe_images_output.images() {
-                Some(join_all(images.iter().map(|image| Info::new(self.client, image))).await)
+                Some(skip(
+                    join_all(images.iter().map(|image| async move {
+                        let launch = match launches.get(&image_id(image)) {
+                            Some(launch) => *launch,
+                            None => last_launched_time(client, image_id(image))
+                                .await
+                                .unwrap_or_else(|error| {
+                                    report(&error);
+                                    None
+                                }),
+                        };
+                        Info::new(client, image, launch.as_ref(), grouping, cascade).await
+                    }))
+                    .await,
+                ))
             } else {
                 None
             },
@@ -315,7 +523,105 @@ impl<'a> Builder<'a> {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+impl Resource for Image {
+    fn id(&self) -> &str {
+        self.image_id().unwrap_or_default()
+    }
+
+    fn name(&self) -> &str {
+        self.name().unwrap_or_default()
+    }
+
+    fn tag(&self, key: &str) -> Option<&str> {
+        self.tags()?
+            .iter()
+            .find(|tag| tag.key() == Some(key))?
+            .value()
+    }
+
+    fn attribute(&self, name: &str) -> Option<Value> {
+        let ebs = || {
+            self.block_device_mappings()
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|mapping| mapping.ebs())
+        };
+
+        Some(match name {
+            "id" => Value::Text(Resource::id(self).to_owned()),
+            "name" => Value::Text(Resource::name(self).to_owned()),
+            "age" => age(creation_date(self).ok()?.timestamp()),
+            "created" => Value::Number(creation_date(self).ok()?.timestamp() as f64),
+            "size" => Value::Number(
+                ebs()
+                    .filter_map(|ebs| ebs.volume_size())
+                    .map(f64::from)
+                    .sum(),
+            ),
+            "encrypted" => Value::Bool(ebs().all(|ebs| ebs.encrypted().unwrap_or_default())),
+            "state" => Value::Text(self.state()?.as_str().to_owned()),
+            "type" => Value::Text(self.image_type()?.as_str().to_owned()),
+            "description" => Value::Text(self.description()?.to_owned()),
+            _ => return None,
+        })
+    }
+}
+
+fn image_id(image: &Image) -> String {
+    image.image_id().unwrap_or(UNKNOWN_ID).to_owned()
+}
+
+fn creation_date(image: &Image) -> Result<DateTime<Utc>> {
+    let value = image
+        .creation_date()
+        .ok_or_else(|| Error::MissingAttribute {
+            id: image_id(image),
+            attribute: "CreationDate",
+        })?;
+
+    value.parse().map_err(|source| Error::InvalidDate {
+        id: image_id(image),
+        value: value.to_owned(),
+        source,
+    })
+}
+
+/// Last time an instance was launched from the image, `None` if never or before 2017.
+async fn last_launched_time(client: &Client, id: String) -> Result<Option<DateTime<Utc>>> {
+    let output = client
+        .describe_image_attribute()
+        .image_id(&id)
+        .attribute(ImageAttributeName::LastLaunchedTime)
+        .send()
+        .await?;
+
+    output
+        .last_launched_time()
+        .and_then(|attribute| attribute.value())
+        .map(|value| {
+            value.parse().map_err(|source| Error::InvalidDate {
+                id,
+                value: value.to_owned(),
+                source,
+            })
+        })
+        .transpose()
+}
+
+fn validate(image: &Image) -> Result<()> {
+    let missing = |attribute| Error::MissingAttribute {
+        id: image_id(image),
+        attribute,
+    };
+
+    image.image_id().ok_or_else(|| missing("ImageId"))?;
+    image.name().ok_or_else(|| missing("Name"))?;
+    creation_date(image)?;
+
+    Ok(())
+}
+
+#[derive(Default, Serialize, Deserialize)]
 pub struct Images(Option<Vec<Info>>);
 
 #[derive(Serialize, Deserialize)]
@@ -323,57 +629,366 @@ pub struct Info {
     id: String,
     name: String,
     creation_date: String,
+    /// Missing when never launched, when it could not be looked up, or from older plans.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_launched_time: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    tags: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    architecture: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    platform: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    owner_id: Option<String>,
+    /// Retention group, missing from older plans.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
     snapshots: Option<Vec<Snapshots>>,
+
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    protected: bool,
 }
 
 impl Info {
-    async fn new(client: &Client, image: &Image) -> Self {
+    fn size(&self) -> i64 {
+        self.snapshots.iter().flatten().map(Snapshots::size).sum()
+    }
+
+    /// Plans the backing snapshots, and volumes restored from them, as far as the cascade reaches.
+    async fn new(
+        client: &Client,
+        image: &Image,
+        launch: Option<&DateTime<Utc>>,
+        grouping: &Grouping,
+        cascade: Cascade,
+    ) -> Result<Self> {
         let mut acc: Vec<Snapshots> = Vec::new();
 
-        if let Some(bdms) = image.block_device_mappings() {
+        if let Some(bdms) = image
+            .block_device_mappings()
+            .filter(|_| cascade >= Cascade::Snapshots)
+        {
             for bdm in bdms {
                 if let Some(ebs) = bdm.ebs() {
                     if let Some(snapshot_id) = ebs.snapshot_id() {
-                        if let Ok(builder) = SnapshotsBuilder::new(
-                            &client,
+                        let builder = SnapshotsBuilder::new(
+                            client,
                             DescribeSnapshots::snapshot_ids(Some(vec![snapshot_id.to_string()])),
                         )
-                        .await
-                        {
-                            acc.push(builder.build().await)
-                        };
+                        .await?;
+                        acc.push(
+                            builder
+                                .build(cascade)
+                                .await
+                                .because(&format!("backs {}", image_id(image))),
+                        );
                     }
                 }
             }
         }
 
-        Info {
+        let missing = |attribute| Error::MissingAttribute {
+            id: image_id(image),
+            attribute,
+        };
+
+        Ok(Info {
             id: image
                 .image_id()
-                .expect("Failed to read image ID")
+                .ok_or_else(|| missing("ImageId"))?
                 .to_string(),
-            name: image.name().expect("Failed to read image name").to_string(),
+            name: image.name().ok_or_else(|| missing("Name"))?.to_string(),
             creation_date: image
                 .creation_date()
-                .expect("Failed to read image's creation date")
+                .ok_or_else(|| missing("CreationDate"))?
                 .to_string(),
+            last_launched_time: launch
+                .map(|date| date.to_rfc3339_opts(SecondsFormat::Millis, true)),
+            tags: tags(image.tags()),
+            architecture: image
+                .architecture()
+                .map(|architecture| architecture.as_str().to_owned()),
+            platform: image.platform_details().map(str::to_owned),
+            owner_id: image.owner_id().map(str::to_owned),
+            group: (!matches!(grouping, Grouping::All)).then(|| grouping.image(image)),
             snapshots: Some(acc),
+            protected: false,
+        })
+    }
+
+    pub fn entry(&self) -> Entry {
+        Entry {
+            kind: Kind::Image,
+            id: self.id.clone(),
+            name: self.name.clone(),
+            date: Some(self.creation_date.clone()),
+            size: self.size(),
+            own_size: 0,
+            protected: self.protected,
+            tags: self.tags.clone(),
+            details: details([
+                ("architecture", self.architecture.clone()),
+                ("platform", self.platform.clone()),
+                ("owner", self.owner_id.clone()),
+                ("last_launched", self.last_launched_time.clone()),
+                ("group", self.group.clone()),
+            ]),
+            children: self
+                .snapshots
+                .iter()
+                .flatten()
+                .flat_map(Snapshots::entries)
+                .collect(),
         }
     }
+}
 
-    pub async fn delete(&self, client: &Client) -> Result<()> {
-        client.deregister_image().image_id(&self.id).send().await?;
-        if let Some(snapshots) = &self.snapshots {
-            join_all(snapshots.iter().map(|snapshot| snapshot.cleanup(client))).await;
+/// How images are grouped for retention.
+pub enum Grouping {
+    /// All matching images, as a single group.
+    All,
+    /// First capture group of the pattern applied to the name, whole name otherwise.
+    Name(regex::Regex),
+    /// Value of the tag, empty when missing.
+    Tag(String),
+}
+
+impl Grouping {
+    pub fn group(&self, name: &str, tags: &BTreeMap<String, String>) -> String {
+        match self {
+            Self::All => String::new(),
+            Self::Name(pattern) => pattern
+                .captures(name)
+                .and_then(|captures| captures.get(1))
+                .map_or(name, |group| group.as_str())
+                .to_owned(),
+            Self::Tag(key) => tags.get(key).cloned().unwrap_or_default(),
         }
-        Ok(())
+    }
+
+    fn image(&self, image: &Image) -> String {
+        self.group(image.name().unwrap_or_default(), &tags(image.tags()))
     }
 }
 
+/// Version extracted from an image name or tag.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Version {
+    Semver(semver::Version),
+    Numeric(Vec<u64>),
+    Lexical(String),
+}
+
+/// Where image versions are read from and how they compare.
+pub struct Versioning {
+    pub pattern: Option<regex::Regex>,
+    pub tag: Option<String>,
+    pub order: VersionOrder,
+}
+
+impl Versioning {
+    fn version(&self, image: &Image) -> Option<Version> {
+        let text = match &self.tag {
+            Some(key) => tags(image.tags()).remove(key)?,
+            None => image.name()?.to_owned(),
+        };
+        let text = match &self.pattern {
+            Some(pattern) => pattern.captures(&text)?.get(1)?.as_str().to_owned(),
+            None => text,
+        };
+
+        match self.order {
+            VersionOrder::Semver => semver::Version::parse(text.trim_start_matches('v'))
+                .ok()
+                .map(Version::Semver),
+            VersionOrder::Numeric => {
+                let numbers = text
+                    .split(|c: char| !c.is_ascii_digit())
+                    .filter(|number| !number.is_empty())
+                    .map(str::parse)
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .ok()?;
+                (!numbers.is_empty()).then_some(Version::Numeric(numbers))
+            }
+            VersionOrder::Lexical => Some(Version::Lexical(text)),
+        }
+    }
+}
+
+/// Orders items by version, highest first, ties broken by date, newest first.
+///
+/// Items lacking a version come, newest first, right before the first versioned item older than
+/// them.
+fn by_version<T, V: Ord, D: Ord>(
+    items: Vec<T>,
+    version: impl Fn(&T) -> Option<V>,
+    date: impl Fn(&T) -> D,
+) -> Vec<T> {
+    let (mut versioned, mut unversioned): (Vec<_>, Vec<_>) = items
+        .into_iter()
+        .map(|item| (version(&item), date(&item), item))
+        .partition(|(version, _, _)| version.is_some());
+    versioned.sort_by(|a, b| (&b.0, &b.1).cmp(&(&a.0, &a.1)));
+    unversioned.sort_by(|a, b| b.1.cmp(&a.1));
+
+    let mut sorted = Vec::with_capacity(versioned.len() + unversioned.len());
+    let mut unversioned = unversioned.into_iter().peekable();
+    for (_, date, item) in versioned {
+        while let Some((_, _, newer)) = unversioned.next_if(|(_, other, _)| *other > date) {
+            sorted.push(newer);
+        }
+        sorted.push(item);
+    }
+    sorted.extend(unversioned.map(|(_, _, item)| item));
+    sorted
+}
+
+/// Counts the self-owned images surviving the plan in each of its groups.
+pub async fn inventory(client: &Client, images: &Images, grouping: &Grouping) -> Result<Inventory> {
+    let owned = client
+        .describe_images()
+        .set_owners(Some(vec!["self".to_owned()]))
+        .send()
+        .await?
+        .images
+        .unwrap_or_default();
+
+    let planned = images
+        .pending()
+        .map(|image| image.id.as_str())
+        .collect::<HashSet<_>>();
+
+    let mut survivors = images
+        .pending()
+        .map(|image| (grouping.group(&image.name, &image.tags), 0))
+        .collect::<BTreeMap<_, _>>();
+
+    for image in &owned {
+        if let Some(id) = image.image_id() {
+            if !planned.contains(id) {
+                if let Some(count) = survivors.get_mut(&grouping.image(image)) {
+                    *count += 1;
+                }
+            }
+        }
+    }
+
+    Ok(Inventory {
+        survivors,
+        ..Default::default()
+    })
+}
+
+/// Self-owned images the plan keeps, and the snapshots backing them.
+#[derive(Default)]
+pub struct Survivors {
+    pub images: HashSet<String>,
+    pub snapshots: HashSet<String>,
+}
+
+pub async fn survivors(client: &Client, images: &Images) -> Result<Survivors> {
+    let planned = images
+        .pending()
+        .map(|image| image.id.as_str())
+        .collect::<HashSet<_>>();
+
+    let mut survivors = Survivors::default();
+    for image in client
+        .describe_images()
+        .set_owners(Some(vec!["self".to_owned()]))
+        .send()
+        .await?
+        .images()
+        .unwrap_or_default()
+    {
+        let id = image.image_id().unwrap_or_default();
+        if planned.contains(id) {
+            continue;
+        }
+        survivors.images.insert(id.to_owned());
+        survivors.snapshots.extend(
+            image
+                .block_device_mappings()
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|bdm| bdm.ebs()?.snapshot_id())
+                .map(str::to_owned),
+        );
+    }
+
+    Ok(survivors)
+}
+
 impl Images {
-    pub async fn cleanup(&self, client: &Client) {
-        if let Some(snapshots) = &self.0 {
-            join_all(snapshots.iter().map(|snapshot| snapshot.delete(client))).await;
+    /// Images to delete, protected ones excluded.
+    fn pending(&self) -> impl Iterator<Item = &Info> {
+        self.0.iter().flatten().filter(|image| !image.protected)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending().next().is_none()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending().count()
+    }
+
+    /// Number of images, snapshots and volumes to delete.
+    pub fn count(&self) -> usize {
+        self.pending()
+            .map(|image| {
+                1 + image
+                    .snapshots
+                    .iter()
+                    .flatten()
+                    .map(Snapshots::count)
+                    .sum::<usize>()
+            })
+            .sum()
+    }
+
+    /// Size of the backing snapshots and their volumes, in GiB.
+    pub fn size(&self) -> i64 {
+        self.pending().map(Info::size).sum()
+    }
+
+    pub fn entries(&self) -> Vec<Entry> {
+        self.0.iter().flatten().map(Info::entry).collect()
+    }
+
+    /// Drops skipped images, dependents included, then flags the images protected if given the
+    /// protected set, unflagging the others.
+    pub fn decide(&mut self, skipped: &HashSet<String>, protected: Option<&HashSet<String>>) {
+        if let Some(images) = &mut self.0 {
+            images.retain(|image| !skipped.contains(&image.id));
+            for image in images {
+                if let Some(protected) = protected {
+                    image.protected = protected.contains(&image.id);
+                }
+                for snapshots in image.snapshots.iter_mut().flatten() {
+                    snapshots.decide(skipped, None);
+                }
+            }
+        }
+    }
+
+    /// Appends the images of another plan, but those already planned.
+    pub fn extend(&mut self, other: Self) {
+        let images = self.0.get_or_insert_with(Vec::new);
+        for image in other.0.into_iter().flatten() {
+            if !images.iter().any(|planned| planned.id == image.id) {
+                images.push(image);
+            }
+        }
+    }
+
+    /// Collects the first image, snapshot and volume IDs of the plan.
+    pub fn samples(&self, samples: &mut Samples) {
+        for image in self.pending() {
+            samples.image.get_or_insert_with(|| image.id.clone());
+            for snapshots in image.snapshots.iter().flatten() {
+                snapshots.samples(samples);
+            }
         }
     }
 }
@@ -383,7 +998,67 @@ impl std::fmt::Display for Images {
         writeln!(
             f,
             "{}",
-            serde_json::to_string_pretty(&self).expect("Serialization failure")
+            serde_json::to_string_pretty(&self).map_err(|_| std::fmt::Error)?
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(name: &str) -> Image {
+        Image::builder().name(name).build()
+    }
+
+    fn versioning(pattern: &str, order: VersionOrder) -> Versioning {
+        Versioning {
+            pattern: Some(regex::Regex::new(pattern).unwrap()),
+            tag: None,
+            order,
+        }
+    }
+
+    #[test]
+    fn versions_compare_by_order() {
+        let semver = versioning(r"^app-(.*)$", VersionOrder::Semver);
+        assert!(semver.version(&image("app-v1.10.0")) > semver.version(&image("app-1.9.2")));
+        assert_eq!(semver.version(&image("app-latest")), None);
+
+        let numeric = versioning(r"^app-(.*)$", VersionOrder::Numeric);
+        assert!(numeric.version(&image("app-2.10")) > numeric.version(&image("app-2.9")));
+        assert_eq!(numeric.version(&image("app-x")), None);
+
+        let lexical = versioning(r"^app-(.*)$", VersionOrder::Lexical);
+        assert!(lexical.version(&image("app-2.9")) > lexical.version(&image("app-2.10")));
+        assert_eq!(lexical.version(&image("base-1")), None);
+    }
+
+    #[test]
+    fn versions_rank_first_then_dates() {
+        // (name, version, date)
+        let items = vec![
+            ("a", Some(1), 10),
+            ("b", Some(3), 5),
+            ("c", Some(2), 30),
+            ("d", Some(3), 8),
+        ];
+        let sorted = by_version(items, |item| item.1, |item| item.2);
+        let names = sorted.iter().map(|item| item.0).collect::<Vec<_>>();
+        assert_eq!(names, ["d", "b", "c", "a"]);
+    }
+
+    #[test]
+    fn unversioned_items_slot_in_by_date() {
+        let items = vec![
+            ("v2", Some(2), 20),
+            ("v1", Some(1), 10),
+            ("old", None, 1),
+            ("newest", None, 40),
+            ("middle", None, 15),
+        ];
+        let sorted = by_version(items, |item| item.1, |item| item.2);
+        let names = sorted.iter().map(|item| item.0).collect::<Vec<_>>();
+        assert_eq!(names, ["newest", "v2", "middle", "v1", "old"]);
+    }
+}
@@ -0,0 +1,250 @@
+use crate::{
+    aws::{rfc3339, tags, volume},
+    error::Result,
+    out::details,
+};
+use aws_sdk_ec2::{model::InstanceStateName, Client};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Kind {
+    Image,
+    Snapshot,
+    Volume,
+    Instance,
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format!("{self:?}").to_lowercase())
+    }
+}
+
+pub struct Node {
+    pub kind: Kind,
+    pub id: String,
+    pub name: String,
+    pub date: Option<String>,
+    /// Storage the resource holds, in GiB, 0 for images and instances.
+    pub size: i64,
+    pub tags: BTreeMap<String, String>,
+    /// Kind specific metadata, by column name.
+    pub details: BTreeMap<&'static str, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Relation {
+    /// Image backed by a snapshot.
+    Backs,
+    /// Volume restored from a snapshot.
+    Restores,
+    /// Instance launched from an image.
+    Launches,
+    /// Volume attached to an instance.
+    Attaches,
+}
+
+impl std::fmt::Display for Relation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Backs => "backed by",
+                Self::Restores => "restored",
+                Self::Launches => "launched",
+                Self::Attaches => "attached",
+            }
+        )
+    }
+}
+
+/// Relation between two resources, pointing from images to snapshots, volumes, then instances.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    pub relation: Relation,
+}
+
+/// Self-owned images, snapshots and volumes, and the instances launched from those images or
+/// using those volumes.
+pub struct Lineage {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+fn name(tags: &BTreeMap<String, String>) -> String {
+    tags.get("Name").cloned().unwrap_or_default()
+}
+
+impl Lineage {
+    pub async fn describe(client: &Client) -> Result<Self> {
+        let mut nodes = vec![];
+        let mut edges = BTreeSet::new();
+
+        let images = client
+            .describe_images()
+            .set_owners(Some(vec!["self".to_owned()]))
+            .send()
+            .await?;
+        for image in images.images().unwrap_or_default() {
+            let Some(id) = image.image_id() else {
+                continue;
+            };
+            for snapshot in image
+                .block_device_mappings()
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|bdm| bdm.ebs()?.snapshot_id())
+            {
+                edges.insert(Edge {
+                    from: id.to_owned(),
+                    to: snapshot.to_owned(),
+                    relation: Relation::Backs,
+                });
+            }
+            nodes.push(Node {
+                kind: Kind::Image,
+                id: id.to_owned(),
+                name: image.name().unwrap_or_default().to_owned(),
+                date: image.creation_date().map(str::to_owned),
+                size: 0,
+                tags: tags(image.tags()),
+                details: details([(
+                    "state",
+                    image.state().map(|state| state.as_str().to_owned()),
+                )]),
+            });
+        }
+
+        let snapshots = client.describe_snapshots().owner_ids("self").send().await?;
+        for snapshot in snapshots.snapshots().unwrap_or_default() {
+            let Some(id) = snapshot.snapshot_id() else {
+                continue;
+            };
+            nodes.push(Node {
+                kind: Kind::Snapshot,
+                id: id.to_owned(),
+                name: name(&tags(snapshot.tags())),
+                date: snapshot.start_time().and_then(rfc3339),
+                size: snapshot.volume_size().map_or(0, i64::from),
+                tags: tags(snapshot.tags()),
+                details: details([(
+                    "tier",
+                    snapshot.storage_tier().map(|tier| tier.as_str().to_owned()),
+                )]),
+            });
+        }
+
+        let volumes = client.describe_volumes().send().await?;
+        for volume in volumes.volumes().unwrap_or_default() {
+            let Some(id) = volume.volume_id() else {
+                continue;
+            };
+            if let Some(snapshot) = volume.snapshot_id().filter(|snapshot| !snapshot.is_empty()) {
+                edges.insert(Edge {
+                    from: snapshot.to_owned(),
+                    to: id.to_owned(),
+                    relation: Relation::Restores,
+                });
+            }
+            let instances = volume::instances(volume);
+            for instance in &instances {
+                edges.insert(Edge {
+                    from: id.to_owned(),
+                    to: (*instance).to_owned(),
+                    relation: Relation::Attaches,
+                });
+            }
+            nodes.push(Node {
+                kind: Kind::Volume,
+                id: id.to_owned(),
+                name: name(&tags(volume.tags())),
+                date: volume.create_time().and_then(rfc3339),
+                size: volume.size().map_or(0, i64::from),
+                tags: tags(volume.tags()),
+                details: details([
+                    (
+                        "type",
+                        volume.volume_type().map(|kind| kind.as_str().to_owned()),
+                    ),
+                    (
+                        "state",
+                        volume.state().map(|state| state.as_str().to_owned()),
+                    ),
+                    (
+                        "attached",
+                        Some(instances.join(",")).filter(|ids| !ids.is_empty()),
+                    ),
+                ]),
+            });
+        }
+
+        // Instances launched from self-owned images, or using our volumes whatever their image.
+        let images = nodes
+            .iter()
+            .filter(|node| node.kind == Kind::Image)
+            .map(|node| node.id.clone())
+            .collect::<HashSet<_>>();
+        let attached = edges
+            .iter()
+            .filter(|edge| edge.relation == Relation::Attaches)
+            .map(|edge| edge.to.clone())
+            .collect::<HashSet<_>>();
+        let reservations = client.describe_instances().send().await?;
+        for instance in reservations
+            .reservations()
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|reservation| reservation.instances().unwrap_or_default())
+        {
+            let Some(id) = instance.instance_id() else {
+                continue;
+            };
+            let image = instance.image_id().filter(|image| images.contains(*image));
+            let state = instance.state().and_then(|state| state.name());
+            if (image.is_none() && !attached.contains(id))
+                || state == Some(&InstanceStateName::Terminated)
+            {
+                continue;
+            }
+            if let Some(image) = image {
+                edges.insert(Edge {
+                    from: image.to_owned(),
+                    to: id.to_owned(),
+                    relation: Relation::Launches,
+                });
+            }
+            nodes.push(Node {
+                kind: Kind::Instance,
+                id: id.to_owned(),
+                name: name(&tags(instance.tags())),
+                date: instance.launch_time().and_then(rfc3339),
+                size: 0,
+                tags: tags(instance.tags()),
+                details: details([
+                    (
+                        "type",
+                        instance
+                            .instance_type()
+                            .map(|kind| kind.as_str().to_owned()),
+                    ),
+                    ("state", state.map(|state| state.as_str().to_owned())),
+                ]),
+            });
+        }
+
+        // Drops relations to resources owned by others, or to terminated instances.
+        let known = nodes
+            .iter()
+            .map(|node| node.id.as_str())
+            .collect::<HashSet<_>>();
+        let edges = edges
+            .into_iter()
+            .filter(|edge| known.contains(edge.from.as_str()) && known.contains(edge.to.as_str()))
+            .collect();
+
+        Ok(Self { nodes, edges })
+    }
+}
@@ -1,3 +1,24 @@
+pub mod execution;
 pub mod image;
+pub mod lineage;
+pub mod permission;
+pub mod preflight;
 pub mod snapshot;
 pub mod volume;
+
+use aws_sdk_ec2::{model::Tag, types::DateTime};
+use chrono::{SecondsFormat, Utc};
+use std::collections::BTreeMap;
+
+/// Formats dates the way EC2 formats image creation dates.
+pub fn rfc3339(date: &DateTime) -> Option<String> {
+    chrono::DateTime::<Utc>::from_timestamp(date.secs(), date.subsec_nanos())
+        .map(|date| date.to_rfc3339_opts(SecondsFormat::Millis, true))
+}
+
+pub fn tags(tags: Option<&[Tag]>) -> BTreeMap<String, String> {
+    tags.unwrap_or_default()
+        .iter()
+        .filter_map(|tag| Some((tag.key()?.to_owned(), tag.value()?.to_owned())))
+        .collect()
+}
@@ -0,0 +1,155 @@
+use crate::options::Kind;
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
+
+/// EC2 API actions this tool calls, as named by IAM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Action {
+    DescribeImageAttribute,
+    DescribeImages,
+    DescribeInstances,
+    DescribeSnapshots,
+    DescribeVolumes,
+    DeregisterImage,
+    DeleteSnapshot,
+    DeleteVolume,
+}
+
+impl Action {
+    /// Resources the action applies to, `*` for actions lacking resource-level permissions.
+    fn resource(&self) -> &'static str {
+        match self {
+            Self::DeregisterImage => "arn:aws:ec2:*::image/*",
+            Self::DeleteSnapshot => "arn:aws:ec2:*::snapshot/*",
+            Self::DeleteVolume => "arn:aws:ec2:*:*:volume/*",
+            _ => "*",
+        }
+    }
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ec2:{self:?}")
+    }
+}
+
+impl Serialize for Action {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Sorted, deduplicated union of action sets.
+pub fn union(sets: &[&[Action]]) -> Vec<Action> {
+    let mut actions = sets.concat();
+    actions.sort();
+    actions.dedup();
+    actions
+}
+
+/// Minimal IAM policy document.
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Policy {
+    version: &'static str,
+    statement: Vec<Statement>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Statement {
+    sid: String,
+    effect: &'static str,
+    action: Vec<Action>,
+    resource: Vec<&'static str>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    condition: BTreeMap<&'static str, BTreeMap<String, String>>,
+}
+
+impl Policy {
+    /// Covers planning for the given kinds, and applying when asked to.
+    ///
+    /// Deletions of the outermost kind can be restricted to resources carrying all the given
+    /// tags. Dependents deleted along with them, e.g. the snapshots of an image, rarely carry the
+    /// same tags, so their deletion is left unrestricted.
+    pub fn new(kinds: &[Kind], apply: bool, tags: Vec<(String, String)>) -> Self {
+        let read = union(
+            &kinds
+                .iter()
+                .map(|kind| match kind {
+                    Kind::Image => crate::aws::image::READ,
+                    Kind::Snapshot => crate::aws::snapshot::READ,
+                    Kind::Volume => crate::aws::volume::READ,
+                })
+                .collect::<Vec<_>>(),
+        );
+
+        let mut statement = vec![Statement {
+            sid: "Describe".to_owned(),
+            effect: "Allow",
+            action: read,
+            resource: vec!["*"],
+            condition: BTreeMap::new(),
+        }];
+
+        if apply {
+            let condition = if tags.is_empty() {
+                BTreeMap::new()
+            } else {
+                BTreeMap::from([(
+                    "StringEquals",
+                    tags.into_iter()
+                        .map(|(key, value)| (format!("aws:ResourceTag/{key}"), value))
+                        .collect(),
+                )])
+            };
+
+            let apply = union(
+                &kinds
+                    .iter()
+                    .map(|kind| match kind {
+                        Kind::Image => crate::aws::image::APPLY,
+                        Kind::Snapshot => crate::aws::snapshot::APPLY,
+                        Kind::Volume => crate::aws::volume::APPLY,
+                    })
+                    .collect::<Vec<_>>(),
+            );
+
+            let tagged = if kinds.contains(&Kind::Image) {
+                Action::DeregisterImage
+            } else if kinds.contains(&Kind::Snapshot) {
+                Action::DeleteSnapshot
+            } else {
+                Action::DeleteVolume
+            };
+
+            // One statement per action, resource ARNs differ.
+            statement.extend(apply.into_iter().map(|action| Statement {
+                sid: format!("{action:?}"),
+                effect: "Allow",
+                action: vec![action],
+                resource: vec![action.resource()],
+                condition: if action == tagged {
+                    condition.clone()
+                } else {
+                    BTreeMap::new()
+                },
+            }));
+        }
+
+        Self {
+            version: "2012-10-17",
+            statement,
+        }
+    }
+}
+
+impl std::fmt::Display for Policy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{}",
+            serde_json::to_string_pretty(&self).map_err(|_| std::fmt::Error)?
+        )
+    }
+}
@@ -0,0 +1,206 @@
+use crate::{aws::permission::Action, error::Error, status::Status};
+use aws_sdk_ec2::{model::ImageAttributeName, Client};
+use futures::future::join_all;
+
+/// Resource IDs dry runs are issued against.
+///
+/// Placeholders are used when no resource is at hand: EC2 checks permissions before existence.
+#[derive(Default)]
+pub struct Samples {
+    pub image: Option<String>,
+    pub snapshot: Option<String>,
+    pub volume: Option<String>,
+}
+
+const IMAGE: &str = "ami-00000000000000000";
+const SNAPSHOT: &str = "snap-00000000000000000";
+const VOLUME: &str = "vol-00000000000000000";
+
+impl Samples {
+    /// Picks an existing image, snapshot and volume, if any.
+    pub async fn describe(client: &Client) -> Self {
+        let image = client
+            .describe_images()
+            .owners("self")
+            .send()
+            .await
+            .ok()
+            .and_then(|output| {
+                output
+                    .images()
+                    .and_then(|images| images.first())
+                    .and_then(|image| image.image_id().map(str::to_owned))
+            });
+
+        let snapshot = client
+            .describe_snapshots()
+            .owner_ids("self")
+            .max_results(5)
+            .send()
+            .await
+            .ok()
+            .and_then(|output| {
+                output
+                    .snapshots()
+                    .and_then(|snapshots| snapshots.first())
+                    .and_then(|snapshot| snapshot.snapshot_id().map(str::to_owned))
+            });
+
+        let volume = client
+            .describe_volumes()
+            .max_results(5)
+            .send()
+            .await
+            .ok()
+            .and_then(|output| {
+                output
+                    .volumes()
+                    .and_then(|volumes| volumes.first())
+                    .and_then(|volume| volume.volume_id().map(str::to_owned))
+            });
+
+        Self {
+            image,
+            snapshot,
+            volume,
+        }
+    }
+}
+
+pub enum Verdict {
+    Allowed,
+    Denied(String),
+    /// EC2 answered something else than a dry run outcome.
+    Inconclusive(String),
+}
+
+pub struct Check {
+    pub region: String,
+    pub action: Action,
+    pub verdict: Verdict,
+}
+
+impl std::fmt::Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.verdict {
+            Verdict::Allowed => write!(f, "{}\t{}\tallowed", self.region, self.action),
+            Verdict::Denied(reason) => {
+                write!(f, "{}\t{}\tdenied: {reason}", self.region, self.action)
+            }
+            Verdict::Inconclusive(reason) => {
+                write!(
+                    f,
+                    "{}\t{}\tinconclusive: {reason}",
+                    self.region, self.action
+                )
+            }
+        }
+    }
+}
+
+impl Check {
+    pub fn is_denied(&self) -> bool {
+        matches!(self.verdict, Verdict::Denied(_))
+    }
+}
+
+/// Issues a dry run of each action, against the sample resources.
+pub async fn check(
+    client: &Client,
+    region: &str,
+    actions: &[Action],
+    samples: &Samples,
+) -> Vec<Check> {
+    join_all(actions.iter().map(|action| async move {
+        Check {
+            region: region.to_owned(),
+            action: *action,
+            verdict: verdict(dry_run(client, *action, samples).await),
+        }
+    }))
+    .await
+}
+
+async fn dry_run(client: &Client, action: Action, samples: &Samples) -> Error {
+    let image = samples.image.as_deref().unwrap_or(IMAGE);
+    let snapshot = samples.snapshot.as_deref().unwrap_or(SNAPSHOT);
+    let volume = samples.volume.as_deref().unwrap_or(VOLUME);
+
+    // A dry run never succeeds: EC2 answers `DryRunOperation` when the call would have.
+    match action {
+        Action::DescribeImages => client
+            .describe_images()
+            .dry_run(true)
+            .owners("self")
+            .image_ids(image)
+            .send()
+            .await
+            .err()
+            .map(Error::from),
+        Action::DescribeImageAttribute => client
+            .describe_image_attribute()
+            .dry_run(true)
+            .image_id(image)
+            .attribute(ImageAttributeName::LastLaunchedTime)
+            .send()
+            .await
+            .err()
+            .map(Error::from),
+        Action::DescribeInstances => client
+            .describe_instances()
+            .dry_run(true)
+            .send()
+            .await
+            .err()
+            .map(Error::from),
+        Action::DescribeSnapshots => client
+            .describe_snapshots()
+            .dry_run(true)
+            .snapshot_ids(snapshot)
+            .send()
+            .await
+            .err()
+            .map(Error::from),
+        Action::DescribeVolumes => client
+            .describe_volumes()
+            .dry_run(true)
+            .volume_ids(volume)
+            .send()
+            .await
+            .err()
+            .map(Error::from),
+        Action::DeregisterImage => client
+            .deregister_image()
+            .dry_run(true)
+            .image_id(image)
+            .send()
+            .await
+            .err()
+            .map(Error::from),
+        Action::DeleteSnapshot => client
+            .delete_snapshot()
+            .dry_run(true)
+            .snapshot_id(snapshot)
+            .send()
+            .await
+            .err()
+            .map(Error::from),
+        Action::DeleteVolume => client
+            .delete_volume()
+            .dry_run(true)
+            .volume_id(volume)
+            .send()
+            .await
+            .err()
+            .map(Error::from),
+    }
+    .unwrap_or(Error::DryRunIgnored(action))
+}
+
+fn verdict(error: Error) -> Verdict {
+    match error.service().and_then(|service| service.code) {
+        Some("DryRunOperation") => Verdict::Allowed,
+        _ if error.status() == Status::Unauthorized => Verdict::Denied(error.report()),
+        _ => Verdict::Inconclusive(error.report()),
+    }
+}
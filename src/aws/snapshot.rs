@@ -1,6 +1,16 @@
 use crate::{
-    aws::volume::{Builder as VolumesBuilder, DescribeVolumes, Volumes},
-    error::Result,
+    aws::{
+        image::Survivors,
+        permission::Action,
+        preflight::Samples,
+        rfc3339, tags,
+        volume::{Builder as VolumesBuilder, DescribeVolumes, Volumes},
+    },
+    error::{skip, Error, Result, UNKNOWN_ID},
+    filter::{age, Resource, Selection, Value},
+    options::{Cascade, Kind},
+    out::{details, Entry},
+    retention::{Rule, Schedule},
 };
 use aws_sdk_ec2::{
     model::{Filter, Snapshot},
@@ -9,11 +19,21 @@ use aws_sdk_ec2::{
 };
 use futures::future::join_all;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Actions needed to plan snapshot cleanups, restored volumes lookup included.
+pub const READ: &[Action] = &[Action::DescribeSnapshots, Action::DescribeVolumes];
+
+/// Actions needed to apply snapshot cleanups, restored volumes deletion included.
+pub const APPLY: &[Action] = &[Action::DeleteSnapshot, Action::DeleteVolume];
 
 #[derive(Default)]
 pub struct DescribeSnapshots {
     pub names: Option<Vec<String>>,
     pub snapshot_ids: Option<Vec<String>>,
+    pub filters: Vec<Filter>,
+    /// Any snapshot the caller can access when missing, public ones included.
+    pub owner_ids: Option<Vec<String>>,
 }
 
 impl DescribeSnapshots {
@@ -30,6 +50,17 @@ impl DescribeSnapshots {
             ..Default::default()
         }
     }
+
+    pub fn filters(self, filters: Vec<Filter>) -> Self {
+        Self { filters, ..self }
+    }
+
+    pub fn owned(self) -> Self {
+        Self {
+            owner_ids: Some(vec!["self".to_owned()]),
+            ..self
+        }
+    }
 }
 
 struct Filters(Option<Vec<Filter>>);
@@ -56,6 +87,8 @@ impl From<DescribeSnapshots> for Filters {
             );
         }
 
+        filters.extend(describe_snapshots.filters);
+
         Filters(Some(filters))
     }
 }
@@ -74,29 +107,191 @@ impl<'a> Builder<'a> {
             client,
             output: client
                 .describe_snapshots()
+                .set_owner_ids(describe_snapshots.owner_ids.clone())
                 .set_filters(Filters::from(describe_snapshots).0)
                 .send()
                 .await?,
         })
     }
 
-    pub async fn build(self) -> Snapshots {
+    /// Number of resources still candidates.
+    pub fn candidates(&self) -> usize {
+        self.output.snapshots().map_or(0, <[_]>::len)
+    }
+
+    /// Whether the snapshot is still a candidate.
+    pub fn contains(&self, id: &str) -> bool {
+        self.output
+            .snapshots()
+            .unwrap_or_default()
+            .iter()
+            .any(|snapshot| snapshot.snapshot_id() == Some(id))
+    }
+
+    pub fn filter(self, selection: &Selection) -> Self {
+        let output = DescribeSnapshotsOutput::builder()
+            .set_snapshots(self.output.snapshots.map(|snapshots| {
+                snapshots
+                    .into_iter()
+                    .filter(|snapshot| selection.matches(snapshot))
+                    .collect::<Vec<_>>()
+            }))
+            .build();
+
+        if let Some(snapshots) = output.snapshots() {
+            log::info!("Kept {} after filtering", snapshots.len());
+        }
+
+        Self {
+            client: self.client,
+            output,
+        }
+    }
+
+    /// Keeps snapshots created for an image that is gone or planned for deletion.
+    ///
+    /// Snapshots backing a surviving image, or already deleted along with one, are dropped.
+    pub fn orphaned(self, survivors: &Survivors, planned: &HashSet<String>) -> Self {
+        let output = DescribeSnapshotsOutput::builder()
+            .set_snapshots(self.output.snapshots.map(|snapshots| {
+                snapshots
+                    .into_iter()
+                    .filter(|snapshot| {
+                        let id = snapshot.snapshot_id().unwrap_or_default();
+                        image_id(snapshot.description().unwrap_or_default())
+                            .is_some_and(|image| !survivors.images.contains(image))
+                            && !survivors.snapshots.contains(id)
+                            && !planned.contains(id)
+                    })
+                    .collect::<Vec<_>>()
+            }))
+            .build();
+
+        if let Some(snapshots) = output.snapshots() {
+            log::info!("{} of them are orphaned", snapshots.len());
+        }
+
+        Self {
+            client: self.client,
+            output,
+        }
+    }
+
+    /// Drops the snapshots of each volume kept by the schedule, returning them with their rule.
+    ///
+    /// Snapshots lacking a start time are kept as well, as their age is unknown.
+    pub fn gfs(self, schedule: &Schedule) -> (Self, BTreeMap<String, Rule>) {
+        let mut retained = BTreeMap::new();
+
+        let snapshots = self.output.snapshots.map(|snapshots| {
+            let mut groups = HashMap::<_, Vec<_>>::new();
+            for snapshot in snapshots {
+                match snapshot.start_time().and_then(|date| {
+                    chrono::DateTime::from_timestamp(date.secs(), date.subsec_nanos())
+                }) {
+                    Some(date) => groups
+                        .entry(snapshot.volume_id().unwrap_or_default().to_owned())
+                        .or_default()
+                        .push((snapshot, date)),
+                    None => log::warn!(
+                        "Keeping {}, its start time is unknown",
+                        snapshot.snapshot_id().unwrap_or(UNKNOWN_ID)
+                    ),
+                }
+            }
+
+            let mut candidates = vec![];
+            for snapshots in groups.into_values() {
+                let (snapshots, dates): (Vec<_>, Vec<_>) = snapshots.into_iter().unzip();
+                for (snapshot, rule) in std::iter::zip(snapshots, schedule.retain(&dates)) {
+                    match rule {
+                        Some(rule) => {
+                            let id = snapshot.snapshot_id().unwrap_or(UNKNOWN_ID).to_owned();
+                            log::info!("Keeping {id} as {rule}");
+                            retained.insert(id, rule);
+                        }
+                        None => candidates.push(snapshot),
+                    }
+                }
+            }
+            candidates.sort_by_key(|snapshot| snapshot.start_time().map(|date| date.secs()));
+            candidates
+        });
+
+        log::info!(
+            "Will delete {} snapshots and associated volumes",
+            snapshots.iter().flatten().count()
+        );
+
+        (
+            Self {
+                client: self.client,
+                output: DescribeSnapshotsOutput::builder()
+                    .set_snapshots(snapshots)
+                    .build(),
+            },
+            retained,
+        )
+    }
+
+    pub async fn build(self, cascade: Cascade) -> Snapshots {
         Snapshots(if let Some(snapshots) = self.output.snapshots() {
-            Some(
+            Some(skip(
                 join_all(
                     snapshots
                         .iter()
-                        .map(|snapshot| Info::new(self.client, snapshot)),
+                        .map(|snapshot| Info::new(self.client, snapshot, cascade)),
                 )
                 .await,
-            )
+            ))
         } else {
             None
         })
     }
 }
 
-#[derive(Serialize, Deserialize)]
+/// First image ID of a description, such as `Created by CreateImage(i-0123) for ami-0456`.
+pub fn image_id(description: &str) -> Option<&str> {
+    let start = description.find("ami-")?;
+    let id = &description[start..];
+    let end = id[4..]
+        .find(|c: char| !c.is_ascii_hexdigit())
+        .map_or(id.len(), |end| end + 4);
+    (end > 4).then(|| &id[..end])
+}
+
+impl Resource for Snapshot {
+    fn id(&self) -> &str {
+        self.snapshot_id().unwrap_or_default()
+    }
+
+    fn name(&self) -> &str {
+        self.tag("Name").unwrap_or_default()
+    }
+
+    fn tag(&self, key: &str) -> Option<&str> {
+        self.tags()?
+            .iter()
+            .find(|tag| tag.key() == Some(key))?
+            .value()
+    }
+
+    fn attribute(&self, name: &str) -> Option<Value> {
+        Some(match name {
+            "id" => Value::Text(Resource::id(self).to_owned()),
+            "name" => Value::Text(Resource::name(self).to_owned()),
+            "age" => age(self.start_time()?.secs()),
+            "created" => Value::Number(self.start_time()?.secs() as f64),
+            "size" => Value::Number(self.volume_size()?.into()),
+            "encrypted" => Value::Bool(self.encrypted()?),
+            "state" => Value::Text(self.state()?.as_str().to_owned()),
+            "description" => Value::Text(self.description()?.to_owned()),
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
 pub struct Snapshots(Option<Vec<Info>>);
 
 #[derive(Serialize, Deserialize)]
@@ -104,62 +299,203 @@ pub struct Info {
     id: String,
     name: String,
     size: i32,
+    #[serde(default)]
+    start_time: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    tags: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    storage_tier: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encrypted: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kms_key_id: Option<String>,
+    /// Why the snapshot is deleted along with its parent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
     volumes: Option<Vec<Volumes>>,
+
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    protected: bool,
 }
 
 impl Info {
-    pub async fn new(client: &Client, snapshot: &Snapshot) -> Self {
+    fn size(&self) -> i64 {
+        i64::from(self.size)
+            + self
+                .volumes
+                .iter()
+                .flatten()
+                .map(Volumes::size)
+                .sum::<i64>()
+    }
+
+    /// Plans the available volumes restored from the snapshot too, if the cascade reaches them.
+    pub async fn new(client: &Client, snapshot: &Snapshot, cascade: Cascade) -> Result<Self> {
+        let missing = |attribute| Error::MissingAttribute {
+            id: snapshot.snapshot_id().unwrap_or(UNKNOWN_ID).to_owned(),
+            attribute,
+        };
+
+        let id = snapshot
+            .snapshot_id()
+            .ok_or_else(|| missing("SnapshotId"))?
+            .to_string();
+        let size = snapshot
+            .volume_size()
+            .ok_or_else(|| missing("VolumeSize"))?;
+
         let mut acc: Vec<Volumes> = Vec::new();
 
-        if let Ok(builder) = VolumesBuilder::new(
-            &client,
-            DescribeVolumes::snapshot_ids(Some(vec![snapshot
-                .snapshot_id()
-                .expect("Failed to read snapshot's ID")
-                .to_string()])),
-        )
-        .await
-        {
-            acc.push(builder.build().await)
+        if cascade == Cascade::Volumes {
+            let builder = VolumesBuilder::new(
+                client,
+                DescribeVolumes::snapshot_ids(Some(vec![id.clone()])),
+            )
+            .await?;
+            acc.push(
+                builder
+                    .build()
+                    .await
+                    .because(&format!("restored from {id}")),
+            );
         }
 
-        Self {
-            id: snapshot
-                .snapshot_id()
-                .expect("Failed to read snapshot ID")
-                .to_string(),
+        Ok(Self {
+            id,
             name: snapshot
                 .tags()
                 .unwrap_or(&[])
                 .iter()
-                .find(|tag| tag.key() == Some(&"Name".to_owned()))
-                .map(|tag| tag.value().unwrap())
+                .find(|tag| tag.key() == Some("Name"))
+                .and_then(|tag| tag.value())
                 .unwrap_or("")
                 .to_string(),
-            size: snapshot
-                .volume_size()
-                .expect("Failed to read snapshot's size"),
+            size,
+            start_time: snapshot.start_time().and_then(rfc3339),
+            tags: tags(snapshot.tags()),
+            description: snapshot.description().map(str::to_owned),
+            storage_tier: snapshot.storage_tier().map(|tier| tier.as_str().to_owned()),
+            encrypted: snapshot.encrypted(),
+            kms_key_id: snapshot.kms_key_id().map(str::to_owned),
+            reason: None,
             volumes: Some(acc),
-        }
+            protected: false,
+        })
     }
 
-    pub async fn delete(&self, client: &Client) -> Result<()> {
-        client
-            .delete_snapshot()
-            .snapshot_id(&self.id)
-            .send()
-            .await?;
-        if let Some(volumes) = &self.volumes {
-            join_all(volumes.iter().map(|volume| volume.cleanup(client))).await;
+    pub fn entry(&self) -> Entry {
+        Entry {
+            kind: Kind::Snapshot,
+            id: self.id.clone(),
+            name: self.name.clone(),
+            date: self.start_time.clone(),
+            size: self.size(),
+            own_size: i64::from(self.size),
+            protected: self.protected,
+            tags: self.tags.clone(),
+            details: details([
+                ("description", self.description.clone()),
+                ("tier", self.storage_tier.clone()),
+                (
+                    "encrypted",
+                    self.encrypted.map(|encrypted| encrypted.to_string()),
+                ),
+                ("kms_key", self.kms_key_id.clone()),
+                ("reason", self.reason.clone()),
+            ]),
+            children: self
+                .volumes
+                .iter()
+                .flatten()
+                .flat_map(Volumes::entries)
+                .collect(),
         }
-        Ok(())
     }
 }
 
 impl Snapshots {
-    pub async fn cleanup(&self, client: &Client) {
-        if let Some(snapshots) = &self.0 {
-            join_all(snapshots.iter().map(|volume| volume.delete(client))).await;
+    /// Snapshots to delete, protected ones excluded.
+    fn pending(&self) -> impl Iterator<Item = &Info> {
+        self.0
+            .iter()
+            .flatten()
+            .filter(|snapshot| !snapshot.protected)
+    }
+
+    /// Records why the snapshots are deleted along with their parent.
+    pub fn because(mut self, reason: &str) -> Self {
+        for snapshot in self.0.iter_mut().flatten() {
+            snapshot.reason = Some(reason.to_owned());
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending().next().is_none()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending().count()
+    }
+
+    /// Number of snapshots and volumes to delete.
+    pub fn count(&self) -> usize {
+        self.pending()
+            .map(|snapshot| {
+                1 + snapshot
+                    .volumes
+                    .iter()
+                    .flatten()
+                    .map(Volumes::count)
+                    .sum::<usize>()
+            })
+            .sum()
+    }
+
+    /// Size of the snapshots and their volumes, in GiB.
+    pub fn size(&self) -> i64 {
+        self.pending().map(Info::size).sum()
+    }
+
+    pub fn entries(&self) -> Vec<Entry> {
+        self.0.iter().flatten().map(Info::entry).collect()
+    }
+
+    /// Drops skipped snapshots, dependents included, then flags the snapshots protected if given
+    /// the protected set, unflagging the others.
+    pub fn decide(&mut self, skipped: &HashSet<String>, protected: Option<&HashSet<String>>) {
+        if let Some(snapshots) = &mut self.0 {
+            snapshots.retain(|snapshot| !skipped.contains(&snapshot.id));
+            for snapshot in snapshots {
+                if let Some(protected) = protected {
+                    snapshot.protected = protected.contains(&snapshot.id);
+                }
+                for volumes in snapshot.volumes.iter_mut().flatten() {
+                    volumes.decide(skipped, None);
+                }
+            }
+        }
+    }
+
+    /// Appends the snapshots of another plan, but those already planned.
+    pub fn extend(&mut self, other: Self) {
+        let snapshots = self.0.get_or_insert_with(Vec::new);
+        for snapshot in other.0.into_iter().flatten() {
+            if !snapshots.iter().any(|planned| planned.id == snapshot.id) {
+                snapshots.push(snapshot);
+            }
+        }
+    }
+
+    /// Collects the first snapshot and volume IDs of the plan.
+    pub fn samples(&self, samples: &mut Samples) {
+        for snapshot in self.pending() {
+            samples.snapshot.get_or_insert_with(|| snapshot.id.clone());
+            for volumes in snapshot.volumes.iter().flatten() {
+                volumes.samples(samples);
+            }
         }
     }
 }
@@ -169,7 +505,7 @@ impl std::fmt::Display for Snapshots {
         writeln!(
             f,
             "{}",
-            serde_json::to_string_pretty(&self).expect("Serialization failure")
+            serde_json::to_string_pretty(&self).map_err(|_| std::fmt::Error)?
         )
     }
 }
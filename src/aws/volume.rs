@@ -1,4 +1,10 @@
-use crate::error::Result;
+use crate::{
+    aws::{permission::Action, preflight::Samples, rfc3339, tags},
+    error::{skip, Error, Result, UNKNOWN_ID},
+    filter::{age, Resource, Selection, Value},
+    options::Kind,
+    out::{details, Entry},
+};
 use aws_sdk_ec2::{
     model::{Filter, Volume},
     output::DescribeVolumesOutput,
@@ -6,11 +12,32 @@ use aws_sdk_ec2::{
 };
 use futures::future::join_all;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+/// Actions needed to plan volume cleanups.
+pub const READ: &[Action] = &[Action::DescribeVolumes];
+
+/// Actions needed to apply volume cleanups.
+pub const APPLY: &[Action] = &[Action::DeleteVolume];
+
+/// State of the volumes planned, nothing using them.
+pub const AVAILABLE: &str = "available";
+
+/// Instances the volume is attached to.
+pub fn instances(volume: &Volume) -> Vec<&str> {
+    volume
+        .attachments()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|attachment| attachment.instance_id())
+        .collect()
+}
 
 #[derive(Default)]
 pub struct DescribeVolumes {
     names: Option<Vec<String>>,
     snapshot_ids: Option<Vec<String>>,
+    filters: Vec<Filter>,
 }
 
 impl DescribeVolumes {
@@ -27,6 +54,10 @@ impl DescribeVolumes {
             ..Default::default()
         }
     }
+
+    pub fn filters(self, filters: Vec<Filter>) -> Self {
+        Self { filters, ..self }
+    }
 }
 
 struct Filters(Option<Vec<Filter>>);
@@ -35,7 +66,7 @@ impl From<DescribeVolumes> for Filters {
     fn from(describe_volumes: DescribeVolumes) -> Self {
         let mut filters = vec![Filter::builder()
             .set_name(Some("status".to_owned()))
-            .set_values(Some(vec!["available".to_owned()]))
+            .set_values(Some(vec![AVAILABLE.to_owned()]))
             .build()];
 
         if describe_volumes.snapshot_ids.is_some() {
@@ -56,6 +87,8 @@ impl From<DescribeVolumes> for Filters {
             )
         }
 
+        filters.extend(describe_volumes.filters);
+
         Filters(Some(filters))
     }
 }
@@ -67,6 +100,20 @@ pub struct Builder<'a> {
 
 impl<'a> Builder<'a> {
     pub async fn new(client: &'a Client, describe_volumes: DescribeVolumes) -> Result<Builder<'a>> {
+        // No snapshot means no volume restored from one, EC2 rejecting empty filters.
+        if describe_volumes
+            .snapshot_ids
+            .as_ref()
+            .is_some_and(Vec::is_empty)
+        {
+            return Ok(Self {
+                client,
+                output: DescribeVolumesOutput::builder()
+                    .set_volumes(Some(vec![]))
+                    .build(),
+            });
+        }
+
         Ok(Self {
             client,
             output: client
@@ -77,58 +124,261 @@ impl<'a> Builder<'a> {
         })
     }
 
+    /// Number of resources still candidates.
+    pub fn candidates(&self) -> usize {
+        self.output.volumes().map_or(0, <[_]>::len)
+    }
+
+    /// Whether the volume is still a candidate.
+    pub fn contains(&self, id: &str) -> bool {
+        self.output
+            .volumes()
+            .unwrap_or_default()
+            .iter()
+            .any(|volume| volume.volume_id() == Some(id))
+    }
+
+    pub fn filter(self, selection: &Selection) -> Self {
+        let output = DescribeVolumesOutput::builder()
+            .set_volumes(self.output.volumes.map(|volumes| {
+                volumes
+                    .into_iter()
+                    .filter(|volume| selection.matches(volume))
+                    .collect::<Vec<_>>()
+            }))
+            .build();
+
+        if let Some(volumes) = output.volumes() {
+            log::info!("Kept {} after filtering", volumes.len());
+        }
+
+        Self {
+            client: self.client,
+            output,
+        }
+    }
+
+    /// Drops volumes already deleted along with a snapshot.
+    pub fn exclude(self, planned: &HashSet<String>) -> Self {
+        let output = DescribeVolumesOutput::builder()
+            .set_volumes(self.output.volumes.map(|volumes| {
+                volumes
+                    .into_iter()
+                    .filter(|volume| !planned.contains(volume.volume_id().unwrap_or_default()))
+                    .collect::<Vec<_>>()
+            }))
+            .build();
+
+        Self {
+            client: self.client,
+            output,
+        }
+    }
+
     pub async fn build(self) -> Volumes {
         Volumes(if let Some(volumes) = self.output.volumes() {
-            Some(join_all(volumes.iter().map(|volume| Info::new(self.client, volume))).await)
+            Some(skip(
+                join_all(volumes.iter().map(|volume| Info::new(self.client, volume))).await,
+            ))
         } else {
             None
         })
     }
 }
 
+impl Resource for Volume {
+    fn id(&self) -> &str {
+        self.volume_id().unwrap_or_default()
+    }
+
+    fn name(&self) -> &str {
+        self.tag("Name").unwrap_or_default()
+    }
+
+    fn tag(&self, key: &str) -> Option<&str> {
+        self.tags()?
+            .iter()
+            .find(|tag| tag.key() == Some(key))?
+            .value()
+    }
+
+    fn attribute(&self, name: &str) -> Option<Value> {
+        Some(match name {
+            "id" => Value::Text(Resource::id(self).to_owned()),
+            "name" => Value::Text(Resource::name(self).to_owned()),
+            "age" => age(self.create_time()?.secs()),
+            "created" => Value::Number(self.create_time()?.secs() as f64),
+            "size" => Value::Number(self.size()?.into()),
+            "encrypted" => Value::Bool(self.encrypted()?),
+            "state" => Value::Text(self.state()?.as_str().to_owned()),
+            "type" => Value::Text(self.volume_type()?.as_str().to_owned()),
+            "iops" => Value::Number(self.iops()?.into()),
+            "throughput" => Value::Number(self.throughput()?.into()),
+            "zone" => Value::Text(self.availability_zone()?.to_owned()),
+            _ => return None,
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Info {
     id: String,
     name: String,
     size: i32,
+    #[serde(default)]
+    create_time: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    tags: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    availability_zone: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    volume_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    iops: Option<i32>,
+    /// In MiB/s.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    throughput: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encrypted: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kms_key_id: Option<String>,
+    /// Why the volume is deleted along with its parent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    protected: bool,
 }
 
 impl Info {
-    pub async fn new(_client: &Client, volume: &Volume) -> Self {
-        Self {
+    pub async fn new(_client: &Client, volume: &Volume) -> Result<Self> {
+        let missing = |attribute| Error::MissingAttribute {
+            id: volume.volume_id().unwrap_or(UNKNOWN_ID).to_owned(),
+            attribute,
+        };
+
+        Ok(Self {
             id: volume
                 .volume_id()
-                .expect("Failed to read volume ID")
+                .ok_or_else(|| missing("VolumeId"))?
                 .to_string(),
             name: volume
                 .tags()
                 .unwrap_or(&[])
                 .iter()
-                .find(|tag| tag.key() == Some(&"Name".to_owned()))
-                .map(|tag| tag.value().unwrap())
+                .find(|tag| tag.key() == Some("Name"))
+                .and_then(|tag| tag.value())
                 .unwrap_or("")
                 .to_string(),
-            size: volume.size().expect("Failed to read volume's size"),
-        }
+            size: volume.size().ok_or_else(|| missing("Size"))?,
+            create_time: volume.create_time().and_then(rfc3339),
+            tags: tags(volume.tags()),
+            availability_zone: volume.availability_zone().map(str::to_owned),
+            volume_type: volume.volume_type().map(|kind| kind.as_str().to_owned()),
+            iops: volume.iops(),
+            throughput: volume.throughput(),
+            encrypted: volume.encrypted(),
+            kms_key_id: volume.kms_key_id().map(str::to_owned),
+            reason: None,
+            protected: false,
+        })
     }
 
-    pub async fn delete(&self, client: &Client) -> Result<()> {
-        Ok(client
-            .delete_volume()
-            .volume_id(&self.id)
-            .send()
-            .await
-            .and_then(|_| Ok(()))?)
+    pub fn entry(&self) -> Entry {
+        Entry {
+            kind: Kind::Volume,
+            id: self.id.clone(),
+            name: self.name.clone(),
+            date: self.create_time.clone(),
+            size: i64::from(self.size),
+            own_size: i64::from(self.size),
+            protected: self.protected,
+            tags: self.tags.clone(),
+            details: details([
+                ("zone", self.availability_zone.clone()),
+                ("type", self.volume_type.clone()),
+                ("iops", self.iops.map(|iops| iops.to_string())),
+                (
+                    "throughput",
+                    self.throughput.map(|throughput| throughput.to_string()),
+                ),
+                (
+                    "encrypted",
+                    self.encrypted.map(|encrypted| encrypted.to_string()),
+                ),
+                ("kms_key", self.kms_key_id.clone()),
+                ("reason", self.reason.clone()),
+            ]),
+            children: vec![],
+        }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct Volumes(Option<Vec<Info>>);
 
 impl Volumes {
-    pub async fn cleanup(&self, client: &Client) {
-        if let Some(volumes) = &self.0 {
-            join_all(volumes.iter().map(|volume| volume.delete(client))).await;
+    /// Volumes to delete, protected ones excluded.
+    fn pending(&self) -> impl Iterator<Item = &Info> {
+        self.0.iter().flatten().filter(|volume| !volume.protected)
+    }
+
+    /// Records why the volumes are deleted along with their parent.
+    pub fn because(mut self, reason: &str) -> Self {
+        for volume in self.0.iter_mut().flatten() {
+            volume.reason = Some(reason.to_owned());
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending().next().is_none()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending().count()
+    }
+
+    pub fn count(&self) -> usize {
+        self.len()
+    }
+
+    /// Size of the volumes, in GiB.
+    pub fn size(&self) -> i64 {
+        self.pending().map(|volume| i64::from(volume.size)).sum()
+    }
+
+    pub fn entries(&self) -> Vec<Entry> {
+        self.0.iter().flatten().map(Info::entry).collect()
+    }
+
+    /// Drops skipped volumes, then flags the protected ones if given the protected set, unflagging
+    /// the others.
+    pub fn decide(&mut self, skipped: &HashSet<String>, protected: Option<&HashSet<String>>) {
+        if let Some(volumes) = &mut self.0 {
+            volumes.retain(|volume| !skipped.contains(&volume.id));
+            for volume in volumes {
+                if let Some(protected) = protected {
+                    volume.protected = protected.contains(&volume.id);
+                }
+            }
+        }
+    }
+
+    /// Appends the volumes of another plan, but those already planned.
+    pub fn extend(&mut self, other: Self) {
+        let volumes = self.0.get_or_insert_with(Vec::new);
+        for volume in other.0.into_iter().flatten() {
+            if !volumes.iter().any(|planned| planned.id == volume.id) {
+                volumes.push(volume);
+            }
+        }
+    }
+
+    /// Collects the first volume ID of the plan.
+    pub fn samples(&self, samples: &mut Samples) {
+        if let Some(volume) = self.pending().next() {
+            samples.volume.get_or_insert_with(|| volume.id.clone());
         }
     }
 }
@@ -138,7 +388,7 @@ impl std::fmt::Display for Volumes {
         writeln!(
             f,
             "{}",
-            serde_json::to_string_pretty(&self).expect("Serialization failure")
+            serde_json::to_string_pretty(&self).map_err(|_| std::fmt::Error)?
         )
     }
 }
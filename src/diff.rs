@@ -0,0 +1,251 @@
+use crate::{
+    options::DiffFormat,
+    out::{Entry, Out, COLUMNS},
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Resource of a plan, flattened along with the resource it is deleted with.
+struct Item {
+    kind: String,
+    id: String,
+    name: String,
+    parent: Option<String>,
+    /// Column values, `-` when unknown.
+    fields: BTreeMap<String, String>,
+}
+
+fn items(entries: &[Entry], parent: Option<&str>, items: &mut Vec<Item>) {
+    for entry in entries {
+        let fields = COLUMNS
+            .iter()
+            .filter(|column| !matches!(**column, "kind" | "id"))
+            .map(|column| column.to_string())
+            .chain(entry.tags.keys().map(|key| format!("tag:{key}")))
+            .map(|column| {
+                let value = entry.column(&column);
+                (column, value)
+            })
+            .collect();
+        items.push(Item {
+            kind: entry.kind.to_string(),
+            id: entry.id.clone(),
+            name: entry.name.clone(),
+            parent: parent.map(str::to_owned),
+            fields,
+        });
+        self::items(&entry.children, Some(&entry.id), items);
+    }
+}
+
+#[derive(Serialize)]
+struct Change {
+    old: String,
+    new: String,
+}
+
+#[derive(Serialize)]
+struct Resource {
+    kind: String,
+    id: String,
+    name: String,
+    /// Resource it is deleted with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent: Option<String>,
+    /// Fields that differ, by column name.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    changes: BTreeMap<String, Change>,
+}
+
+impl Resource {
+    fn new(item: &Item) -> Self {
+        Self {
+            kind: item.kind.clone(),
+            id: item.id.clone(),
+            name: item.name.clone(),
+            parent: item.parent.clone(),
+            changes: BTreeMap::new(),
+        }
+    }
+}
+
+/// Candidates added, removed and changed from one plan to the next, dependents included.
+#[derive(Serialize)]
+pub struct Diff {
+    added: Vec<Resource>,
+    removed: Vec<Resource>,
+    changed: Vec<Resource>,
+}
+
+impl Diff {
+    pub fn new(old: &Out, new: &Out) -> Self {
+        let (mut before, mut after) = (vec![], vec![]);
+        items(&old.entries(), None, &mut before);
+        items(&new.entries(), None, &mut after);
+
+        let olds = before
+            .iter()
+            .map(|item| (item.id.as_str(), item))
+            .collect::<HashMap<_, _>>();
+        let news = after
+            .iter()
+            .map(|item| (item.id.as_str(), item))
+            .collect::<HashMap<_, _>>();
+
+        let mut changed = vec![];
+        for item in &after {
+            let Some(old) = olds.get(item.id.as_str()) else {
+                continue;
+            };
+
+            let mut resource = Resource::new(item);
+            let unknown = "-".to_owned();
+            let mut columns = old
+                .fields
+                .keys()
+                .chain(item.fields.keys())
+                .collect::<Vec<_>>();
+            columns.sort();
+            columns.dedup();
+            for column in columns {
+                let before = old.fields.get(column).unwrap_or(&unknown);
+                let after = item.fields.get(column).unwrap_or(&unknown);
+                if before != after {
+                    resource.changes.insert(
+                        column.clone(),
+                        Change {
+                            old: before.clone(),
+                            new: after.clone(),
+                        },
+                    );
+                }
+            }
+            if old.parent != item.parent {
+                resource.changes.insert(
+                    "parent".to_owned(),
+                    Change {
+                        old: old.parent.clone().unwrap_or_else(|| unknown.clone()),
+                        new: item.parent.clone().unwrap_or(unknown),
+                    },
+                );
+            }
+
+            if !resource.changes.is_empty() {
+                changed.push(resource);
+            }
+        }
+
+        Self {
+            added: after
+                .iter()
+                .filter(|item| !olds.contains_key(item.id.as_str()))
+                .map(Resource::new)
+                .collect(),
+            removed: before
+                .iter()
+                .filter(|item| !news.contains_key(item.id.as_str()))
+                .map(Resource::new)
+                .collect(),
+            changed,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    pub fn render(&self, format: DiffFormat) -> String {
+        match format {
+            DiffFormat::Human => self.human(),
+            DiffFormat::Json => self.to_string(),
+            DiffFormat::Markdown => self.markdown(),
+        }
+    }
+
+    /// One line per resource, `+` added, `-` removed, `~` changed.
+    fn human(&self) -> String {
+        let line = |sign, resource: &Resource| {
+            let mut line = format!(
+                "{sign} {} {} {:?}",
+                resource.kind, resource.id, resource.name
+            );
+            if let Some(parent) = &resource.parent {
+                line += &format!(", with {parent}");
+            }
+            for (column, change) in &resource.changes {
+                line += &format!("\n    {column}: {} -> {}", change.old, change.new);
+            }
+            line
+        };
+
+        let lines = (self.added.iter().map(|resource| line('+', resource)))
+            .chain(self.removed.iter().map(|resource| line('-', resource)))
+            .chain(self.changed.iter().map(|resource| line('~', resource)))
+            .collect::<Vec<_>>();
+
+        if lines.is_empty() {
+            "No changes\n".to_owned()
+        } else {
+            lines.join("\n") + "\n"
+        }
+    }
+
+    /// A table per section, ready to paste in a review.
+    fn markdown(&self) -> String {
+        let cell = |text: &str| text.replace('|', "\\|");
+        let mut markdown = format!(
+            "## Plan changes\n\n{} added, {} removed, {} changed\n",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len()
+        );
+
+        for (title, resources) in [("Added", &self.added), ("Removed", &self.removed)] {
+            if resources.is_empty() {
+                continue;
+            }
+            markdown += &format!(
+                "\n### {title}\n\n| Kind | ID | Name | With |\n| --- | --- | --- | --- |\n"
+            );
+            for resource in resources {
+                markdown += &format!(
+                    "| {} | `{}` | {} | {} |\n",
+                    resource.kind,
+                    resource.id,
+                    cell(&resource.name),
+                    resource
+                        .parent
+                        .as_deref()
+                        .map_or_else(String::new, |parent| format!("`{parent}`"))
+                );
+            }
+        }
+
+        if !self.changed.is_empty() {
+            markdown += "\n### Changed\n\n| Kind | ID | Field | Old | New |\n| --- | --- | --- | --- | --- |\n";
+            for resource in &self.changed {
+                for (column, change) in &resource.changes {
+                    markdown += &format!(
+                        "| {} | `{}` | {column} | {} | {} |\n",
+                        resource.kind,
+                        resource.id,
+                        cell(&change.old),
+                        cell(&change.new)
+                    );
+                }
+            }
+        }
+
+        markdown
+    }
+}
+
+impl std::fmt::Display for Diff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{}",
+            serde_json::to_string_pretty(&self).map_err(|_| std::fmt::Error)?
+        )
+    }
+}
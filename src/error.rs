@@ -2,9 +2,12 @@ use aws_sdk_ec2::{
     error::DescribeInstancesError,
     error::{DeleteSnapshotError, DescribeSnapshotsError},
     error::{DeleteVolumeError, DescribeVolumesError},
-    error::{DeregisterImageError, DescribeImagesError},
+    error::{DeregisterImageError, DescribeImageAttributeError, DescribeImagesError},
     types::SdkError,
 };
+use std::{path::PathBuf, sync::Mutex};
+
+use crate::{aws::permission::Action, status::Status};
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -14,6 +17,9 @@ pub enum Error {
     #[error(transparent)]
     DescribeImages(#[from] SdkError<DescribeImagesError>),
 
+    #[error(transparent)]
+    DescribeImageAttribute(#[from] SdkError<DescribeImageAttributeError>),
+
     #[error(transparent)]
     DeregisterImage(#[from] SdkError<DeregisterImageError>),
 
@@ -28,6 +34,295 @@ pub enum Error {
 
     #[error(transparent)]
     DeleteVolume(#[from] SdkError<DeleteVolumeError>),
+
+    #[error("I/O failure on {path:?}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Serialization failure: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("Invalid regex {pattern:?}: {source}")]
+    InvalidRegex {
+        pattern: String,
+        source: regex::Error,
+    },
+
+    #[error("Invalid date {value:?} on {id}: {source}")]
+    InvalidDate {
+        id: String,
+        value: String,
+        source: chrono::ParseError,
+    },
+
+    #[error("Date is out of range")]
+    DateOutOfRange,
+
+    #[error("Invalid tag {0:?}, expected KEY=VALUE")]
+    InvalidTag(String),
+
+    #[error("Invalid filter {filter:?} at {position}: {reason}")]
+    InvalidFilter {
+        filter: String,
+        position: usize,
+        reason: &'static str,
+    },
+
+    #[error("Invalid expression, {reason}:\n  {expression}\n  {}^", " ".repeat(*.position))]
+    InvalidExpression {
+        expression: String,
+        position: usize,
+        reason: String,
+    },
+
+    #[error("Unknown column {0:?}, expected tag:KEY or one of {}", crate::out::COLUMNS.join(", "))]
+    InvalidColumn(String),
+
+    #[error("Unknown timezone {0:?}, expected an IANA name such as Europe/Paris")]
+    InvalidTimezone(String),
+
+    #[error("Retention schedule keeps nothing, give --daily, --weekly, --monthly or --yearly")]
+    EmptySchedule,
+
+    #[error("Plan {0:?} lacks a digest or was modified since it was written")]
+    InvalidDigest(PathBuf),
+
+    #[error("Journal {0:?} was recorded applying another version of the plan, cannot resume")]
+    StaleJournal(PathBuf),
+
+    #[error("Plan {0:?} lacks a valid signature from a trusted key")]
+    Unsigned(PathBuf),
+
+    #[error("Plan {0:?} was reviewed to delete resources its verified version does not")]
+    Widened(PathBuf),
+
+    #[error("Invalid ed25519 key {0:?}, expected 64 hex digits")]
+    InvalidKey(String),
+
+    #[error("Unknown resource {0:?}, expected an ami-, snap- or vol- ID")]
+    UnknownResource(String),
+
+    #[error("{flag} is not supported by {command}")]
+    Unsupported {
+        flag: &'static str,
+        command: &'static str,
+    },
+
+    #[error("Review decisions would be lost, save them with -o/--output or --apply them")]
+    UnsavedReview,
+
+    #[error("Failed to prompt: {0}")]
+    Prompt(std::io::Error),
+
+    #[error("Terminal failure: {0}")]
+    Terminal(std::io::Error),
+
+    #[error("Plan exceeds safety limits, use --force to proceed anyway:\n{}", .0.join("\n"))]
+    Unsafe(Vec<String>),
+
+    #[error("Missing {attribute} on {id}")]
+    MissingAttribute { id: String, attribute: &'static str },
+
+    #[error("Deregistration of {0} is still not visible, its snapshots were left alone")]
+    StillRegistered(String),
+
+    #[error("{0} was not treated as a dry run")]
+    DryRunIgnored(Action),
+
+    #[error("Missing permissions in {region}: {}", list(.actions))]
+    MissingPermissions {
+        region: String,
+        actions: Vec<Action>,
+    },
+}
+
+fn list(actions: &[Action]) -> String {
+    actions
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+/// Metadata attached by EC2 to a failed call.
+pub struct Service<'a> {
+    pub operation: &'static str,
+    pub code: Option<&'a str>,
+    pub message: Option<&'a str>,
+    pub request_id: Option<&'a str>,
+}
+
+macro_rules! service {
+    ($error:expr, $($variant:ident),*) => {
+        match $error {
+            $(Error::$variant(SdkError::ServiceError { err, .. }) => Some(Service {
+                operation: stringify!($variant),
+                code: err.code(),
+                message: err.message(),
+                request_id: err.request_id(),
+            }),)*
+            _ => None,
+        }
+    };
+}
+
+/// Error codes EC2 answers with when credentials are missing, expired or lack permissions.
+const UNAUTHORIZED: &[&str] = &[
+    "AuthFailure",
+    "ExpiredToken",
+    "InvalidClientTokenId",
+    "OptInRequired",
+    "RequestExpired",
+    "UnauthorizedOperation",
+];
+
+/// Error codes EC2 answers with when deleting a resource that is already gone.
+const NOT_FOUND: &[&str] = &[
+    "InvalidAMIID.NotFound",
+    "InvalidSnapshot.NotFound",
+    "InvalidVolume.NotFound",
+];
+
+impl Error {
+    pub fn service(&self) -> Option<Service<'_>> {
+        service!(
+            self,
+            DescribeInstances,
+            DescribeImages,
+            DescribeImageAttribute,
+            DeregisterImage,
+            DescribeSnapshots,
+            DeleteSnapshot,
+            DescribeVolumes,
+            DeleteVolume
+        )
+    }
+
+    /// Credentials are resolved while building requests, hence construction failures.
+    fn is_unauthorized(&self) -> bool {
+        match self {
+            Self::DescribeInstances(SdkError::ConstructionFailure(_))
+            | Self::DescribeImages(SdkError::ConstructionFailure(_))
+            | Self::DescribeImageAttribute(SdkError::ConstructionFailure(_))
+            | Self::DeregisterImage(SdkError::ConstructionFailure(_))
+            | Self::DescribeSnapshots(SdkError::ConstructionFailure(_))
+            | Self::DeleteSnapshot(SdkError::ConstructionFailure(_))
+            | Self::DescribeVolumes(SdkError::ConstructionFailure(_))
+            | Self::DeleteVolume(SdkError::ConstructionFailure(_)) => true,
+            _ => self
+                .service()
+                .and_then(|service| service.code)
+                .is_some_and(|code| UNAUTHORIZED.contains(&code)),
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.service()
+            .and_then(|service| service.code)
+            .is_some_and(|code| NOT_FOUND.contains(&code))
+    }
+
+    pub fn status(&self) -> Status {
+        match self {
+            Self::Io { .. }
+            | Self::Serialization(_)
+            | Self::InvalidRegex { .. }
+            | Self::DateOutOfRange
+            | Self::InvalidTag(_)
+            | Self::InvalidFilter { .. }
+            | Self::InvalidColumn(_)
+            | Self::InvalidExpression { .. }
+            | Self::InvalidTimezone(_)
+            | Self::EmptySchedule
+            | Self::InvalidDigest(_)
+            | Self::Unsigned(_)
+            | Self::Widened(_)
+            | Self::StaleJournal(_)
+            | Self::InvalidKey(_)
+            | Self::UnknownResource(_)
+            | Self::UnsavedReview
+            | Self::Unsupported { .. }
+            | Self::Unsafe(_) => Status::InvalidInput,
+            Self::MissingPermissions { .. } => Status::Unauthorized,
+            _ if self.is_unauthorized() => Status::Unauthorized,
+            _ => Status::Failure,
+        }
+    }
+
+    /// Human readable description, including EC2 error code and request ID when available.
+    pub fn report(&self) -> String {
+        match self.service() {
+            Some(service) => format!(
+                "{} failed: {} (code: {}, request ID: {})",
+                service.operation,
+                service.message.unwrap_or("no message"),
+                service.code.unwrap_or("unknown"),
+                service.request_id.unwrap_or("unknown"),
+            ),
+            None => self.to_string(),
+        }
+    }
+}
+
+/// Most severe status among the errors reported so far.
+static REPORTED: Mutex<Status> = Mutex::new(Status::Clean);
+
+/// Prints an error the pipeline recovers from, remembering it for the exit code.
+pub fn report(error: &Error) {
+    log::error!("{}", error.report());
+    record(error.status());
+}
+
+pub fn record(status: Status) {
+    if let Ok(mut reported) = REPORTED.lock() {
+        *reported = (*reported).max(status);
+    }
+}
+
+pub fn reported() -> Status {
+    REPORTED
+        .lock()
+        .map_or(Status::Failure, |reported| *reported)
+}
+
+/// Placeholder used in errors for resources lacking an ID.
+pub const UNKNOWN_ID: &str = "<unknown>";
+
+/// Keeps successful results, reporting and dropping the failed ones.
+pub fn skip<T>(results: impl IntoIterator<Item = Result<T>>) -> Vec<T> {
+    results
+        .into_iter()
+        .filter_map(|result| match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                report(&error);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Compiles every pattern, pointing at the first invalid one.
+pub fn regex_set(patterns: &[String]) -> Result<regex::RegexSet> {
+    regex::RegexSet::new(patterns).map_err(|source| {
+        // The set error doesn't tell which pattern is invalid.
+        patterns
+            .iter()
+            .find_map(|pattern| regex(pattern).err())
+            .unwrap_or(Error::InvalidRegex {
+                pattern: patterns.join(" "),
+                source,
+            })
+    })
+}
+
+pub fn regex(pattern: &str) -> Result<regex::Regex> {
+    regex::Regex::new(pattern).map_err(|source| Error::InvalidRegex {
+        pattern: pattern.to_owned(),
+        source,
+    })
+}
@@ -0,0 +1,271 @@
+use crate::{
+    aws::{tags, volume},
+    error::{Error, Result},
+    expression::ATTRIBUTES,
+    filter::{Resource, Value},
+    options::Kind,
+    out::{Entry, Out},
+};
+use aws_sdk_ec2::{model::Filter, Client};
+use chrono::DateTime;
+use std::collections::BTreeMap;
+
+enum Verdict {
+    Kept,
+    Dropped,
+    /// An earlier stage dropped the resource.
+    Unreached,
+}
+
+struct Stage {
+    name: &'static str,
+    verdict: Verdict,
+    /// Options the stage judged the resource with.
+    data: String,
+}
+
+/// Verdicts of the pipeline stages on one resource, in order, along with its attributes.
+///
+/// Without an id, nothing is recorded.
+#[derive(Default)]
+pub struct Trace {
+    id: Option<(Kind, String)>,
+    facts: BTreeMap<String, String>,
+    stages: Vec<Stage>,
+}
+
+impl Trace {
+    pub fn new(id: &str) -> Result<Self> {
+        let kind = match id.split_once('-') {
+            Some(("ami", _)) => Kind::Image,
+            Some(("snap", _)) => Kind::Snapshot,
+            Some(("vol", _)) => Kind::Volume,
+            _ => return Err(Error::UnknownResource(id.to_owned())),
+        };
+        Ok(Self {
+            id: Some((kind, id.to_owned())),
+            ..Default::default()
+        })
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_ref().map(|(_, id)| id.as_str())
+    }
+
+    /// Records whether the resource is still a candidate once a stage of its kind ran.
+    pub fn stage(
+        &mut self,
+        kind: Kind,
+        name: &'static str,
+        data: String,
+        kept: impl FnOnce(&str) -> bool,
+    ) {
+        let Some((_, id)) = self.id.as_ref().filter(|(traced, _)| *traced == kind) else {
+            return;
+        };
+        let kept = kept(id);
+        self.push(name, data, kept);
+    }
+
+    fn push(&mut self, name: &'static str, data: String, kept: bool) {
+        let reached = self
+            .stages
+            .iter()
+            .all(|stage| matches!(stage.verdict, Verdict::Kept));
+        let verdict = if !reached {
+            Verdict::Unreached
+        } else if kept {
+            Verdict::Kept
+        } else {
+            Verdict::Dropped
+        };
+        self.stages.push(Stage {
+            name,
+            verdict,
+            data,
+        });
+    }
+
+    /// Records whether the plan lists the resource, on its own or along with another, then
+    /// whether it is protected.
+    pub fn plan(&mut self, out: &Out) {
+        let Some(id) = self.id().map(str::to_owned) else {
+            return;
+        };
+
+        let found = find(&out.entries(), &id, None).map(|(entry, parent)| {
+            (
+                entry.protected,
+                match parent {
+                    Some(parent) => match entry.details.get("reason") {
+                        Some(reason) => format!("deleted with {parent}, {reason}"),
+                        None => format!("deleted with {parent}"),
+                    },
+                    None => format!("{} {:?}", entry.kind, entry.name),
+                },
+            )
+        });
+        self.push(
+            "plan",
+            found
+                .as_ref()
+                .map_or_else(|| "not listed".to_owned(), |(_, data)| data.clone()),
+            found.is_some(),
+        );
+        self.push(
+            "protection",
+            "decisions of the review".to_owned(),
+            found.is_some_and(|(protected, _)| !protected),
+        );
+    }
+
+    /// Whether every stage kept the resource.
+    pub fn is_planned(&self) -> bool {
+        self.stages
+            .iter()
+            .all(|stage| matches!(stage.verdict, Verdict::Kept))
+    }
+
+    /// Looks the resource up, whatever the pipeline keeps, for the stages to be read against.
+    pub async fn describe(&mut self, client: &Client) -> Result<()> {
+        let Some((kind, id)) = self.id.clone() else {
+            return Ok(());
+        };
+
+        if kind == Kind::Image {
+            let output = client.describe_images().image_ids(&id).send().await?;
+            if let Some(image) = output.images().unwrap_or_default().first() {
+                self.facts(image, tags(image.tags()));
+                self.insert("owner", image.owner_id().map(str::to_owned));
+
+                let instances = client
+                    .describe_instances()
+                    .filters(
+                        Filter::builder()
+                            .name("image-id")
+                            .values(id.as_str())
+                            .build(),
+                    )
+                    .send()
+                    .await?
+                    .reservations()
+                    .unwrap_or_default()
+                    .iter()
+                    .flat_map(|reservation| reservation.instances().unwrap_or_default())
+                    .filter_map(|instance| instance.instance_id())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                self.insert("instances", Some(instances).filter(|ids| !ids.is_empty()));
+            }
+        } else if kind == Kind::Snapshot {
+            let output = client.describe_snapshots().snapshot_ids(&id).send().await?;
+            if let Some(snapshot) = output.snapshots().unwrap_or_default().first() {
+                self.facts(snapshot, tags(snapshot.tags()));
+                self.insert("owner", snapshot.owner_id().map(str::to_owned));
+                self.insert("volume", snapshot.volume_id().map(str::to_owned));
+            }
+        } else {
+            let output = client.describe_volumes().volume_ids(&id).send().await?;
+            if let Some(volume) = output.volumes().unwrap_or_default().first() {
+                self.facts(volume, tags(volume.tags()));
+                self.insert("snapshot", volume.snapshot_id().map(str::to_owned));
+                let instances = volume::instances(volume).join(",");
+                self.insert("attached", Some(instances).filter(|ids| !ids.is_empty()));
+            }
+        }
+
+        if self.facts.is_empty() {
+            log::warn!("{id} was not found");
+        }
+        Ok(())
+    }
+
+    /// Attributes expressions and filters apply to, and tags.
+    fn facts(&mut self, resource: &impl Resource, tags: BTreeMap<String, String>) {
+        for attribute in ATTRIBUTES.iter().filter(|attribute| **attribute != "id") {
+            let value = resource.attribute(attribute).map(|value| match value {
+                Value::Number(secs) if *attribute == "age" => {
+                    format!("{:.1} days", secs / 86400.0)
+                }
+                Value::Number(secs) if *attribute == "created" => {
+                    DateTime::from_timestamp(secs as i64, 0)
+                        .map_or_else(|| secs.to_string(), |date| date.to_rfc3339())
+                }
+                Value::Number(number) => number.to_string(),
+                Value::Bool(bool) => bool.to_string(),
+                Value::Text(text) => text,
+            });
+            self.insert(attribute, value);
+        }
+        for (key, value) in tags {
+            self.facts.insert(format!("tag:{key}"), value);
+        }
+    }
+
+    fn insert(&mut self, key: &str, value: Option<String>) {
+        if let Some(value) = value {
+            self.facts.insert(key.to_owned(), value);
+        }
+    }
+}
+
+/// Entry of the id, along with the one it is deleted with.
+fn find<'a>(
+    entries: &'a [Entry],
+    id: &str,
+    parent: Option<&'a str>,
+) -> Option<(&'a Entry, Option<&'a str>)> {
+    entries.iter().find_map(|entry| {
+        if entry.id == id {
+            Some((entry, parent))
+        } else {
+            find(&entry.children, id, Some(&entry.id))
+        }
+    })
+}
+
+impl std::fmt::Display for Trace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some((kind, id)) = &self.id else {
+            return Ok(());
+        };
+
+        writeln!(f, "{kind} {id}")?;
+        for (key, value) in &self.facts {
+            writeln!(f, "  {key}: {value}")?;
+        }
+        writeln!(f)?;
+
+        for stage in &self.stages {
+            let verdict = match stage.verdict {
+                Verdict::Kept => "kept",
+                Verdict::Dropped => "dropped",
+                Verdict::Unreached => "-",
+            };
+            writeln!(f, "{}\t{verdict}\t{}", stage.name, stage.data)?;
+        }
+        writeln!(f)?;
+
+        match self
+            .stages
+            .iter()
+            .find(|stage| matches!(stage.verdict, Verdict::Dropped))
+        {
+            Some(stage) => writeln!(f, "{id} is not planned, dropped by {}", stage.name),
+            None => writeln!(f, "{id} is planned for deletion"),
+        }
+    }
+}
+
+/// Given options as on the command line, `none` if none was.
+pub fn options(options: &[(&str, Option<&[String]>)]) -> String {
+    let given = options
+        .iter()
+        .filter_map(|(option, values)| Some(format!("{option} {}", (*values)?.join(","))))
+        .collect::<Vec<_>>();
+    if given.is_empty() {
+        "none".to_owned()
+    } else {
+        given.join(" ")
+    }
+}
@@ -0,0 +1,594 @@
+use crate::{
+    error::{Error, Result},
+    filter::{Resource, Value},
+};
+use chrono::{DateTime, NaiveDate};
+use std::cmp::Ordering;
+
+/// Attributes expressions may refer to, besides `tag:KEY`.
+pub const ATTRIBUTES: &[&str] = &[
+    "age",
+    "created",
+    "description",
+    "encrypted",
+    "id",
+    "iops",
+    "name",
+    "size",
+    "state",
+    "throughput",
+    "type",
+    "zone",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Match,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    /// Unquoted text, a value on the right hand side of comparisons only.
+    Word(String),
+    Tag(String),
+    Literal(Value),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Identifier(name) | Self::Word(name) => write!(f, "{name:?}"),
+            Self::Tag(key) => write!(f, "tag:{key:?}"),
+            Self::Literal(Value::Bool(value)) => write!(f, "{value}"),
+            Self::Literal(Value::Number(value)) => write!(f, "{value}"),
+            Self::Literal(Value::Text(value)) => write!(f, "{value:?}"),
+            Self::Op(op) => f.write_str(match op {
+                Op::Eq => "'='",
+                Op::Ne => "'!='",
+                Op::Lt => "'<'",
+                Op::Le => "'<='",
+                Op::Gt => "'>'",
+                Op::Ge => "'>='",
+                Op::Match => "'~'",
+            }),
+            Self::And => f.write_str("AND"),
+            Self::Or => f.write_str("OR"),
+            Self::Not => f.write_str("NOT"),
+            Self::Open => f.write_str("'('"),
+            Self::Close => f.write_str("')'"),
+        }
+    }
+}
+
+enum Operand {
+    Attribute(String),
+    Tag(String),
+    Literal(Value),
+}
+
+enum Node {
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Compare(Operand, Op, Operand),
+    Match(Operand, regex::Regex),
+    /// True booleans, and present attributes or tags otherwise.
+    Truthy(Operand),
+}
+
+/// Boolean expression over resource attributes and tags, such as
+/// `encrypted = false AND size > 100 AND (NOT tag:Owner OR tag:Owner = departed-team)`.
+pub struct Expression(Node);
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Self> {
+        let tokens = lex(text)?;
+        let mut parser = Parser {
+            text,
+            tokens: &tokens,
+            index: 0,
+        };
+        let node = parser.or()?;
+        match parser.tokens.get(parser.index) {
+            Some((position, token)) => Err(parser.error(*position, format!("unexpected {token}"))),
+            None => Ok(Self(node)),
+        }
+    }
+
+    pub fn matches(&self, resource: &impl Resource) -> bool {
+        self.0.evaluate(resource)
+    }
+}
+
+fn error(text: &str, position: usize, reason: String) -> Error {
+    Error::InvalidExpression {
+        expression: text.to_owned(),
+        position,
+        reason,
+    }
+}
+
+/// Seconds in a duration unit.
+fn unit(unit: &str) -> Option<f64> {
+    Some(match unit {
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        "d" => 86400.0,
+        "w" => 604800.0,
+        _ => return None,
+    })
+}
+
+/// ISO date, or RFC 3339 date and time, the text starts with.
+fn date(text: &str) -> Option<&str> {
+    let shape = text
+        .as_bytes()
+        .get(..10)?
+        .iter()
+        .enumerate()
+        .all(|(index, c)| {
+            if index == 4 || index == 7 {
+                *c == b'-'
+            } else {
+                c.is_ascii_digit()
+            }
+        });
+    let end = text
+        .find(|c: char| c.is_whitespace() || matches!(c, '(' | ')'))
+        .unwrap_or(text.len());
+    shape.then(|| &text[..end])
+}
+
+/// Seconds since the epoch, dates meaning midnight UTC.
+fn timestamp(date: &str) -> Option<f64> {
+    let seconds = if date.len() == 10 {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .ok()?
+            .and_hms_opt(0, 0, 0)?
+            .and_utc()
+            .timestamp()
+    } else {
+        DateTime::parse_from_rfc3339(date).ok()?.timestamp()
+    };
+    Some(seconds as f64)
+}
+
+fn lex(text: &str) -> Result<Vec<(usize, Token)>> {
+    let mut tokens = vec![];
+    let mut chars = text.char_indices().peekable();
+
+    let word = |chars: &mut std::iter::Peekable<std::str::CharIndices>| {
+        let mut word = String::new();
+        while let Some((_, c)) =
+            chars.next_if(|(_, c)| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '/'))
+        {
+            word.push(c);
+        }
+        word
+    };
+
+    let quoted =
+        |chars: &mut std::iter::Peekable<std::str::CharIndices>, position: usize, quote: char| {
+            let mut string = String::new();
+            while let Some((_, c)) = chars.next() {
+                match c {
+                    '\\' => match chars.next_if(|(_, next)| *next == quote || *next == '\\') {
+                        Some((_, escaped)) => string.push(escaped),
+                        None => string.push('\\'),
+                    },
+                    c if c == quote => return Ok(string),
+                    c => string.push(c),
+                }
+            }
+            Err(error(text, position, "unterminated quote".to_owned()))
+        };
+
+    while let Some(&(position, c)) = chars.peek() {
+        let token = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '(' | ')' => {
+                chars.next();
+                if c == '(' {
+                    Token::Open
+                } else {
+                    Token::Close
+                }
+            }
+            '"' | '\'' => {
+                chars.next();
+                Token::Literal(Value::Text(quoted(&mut chars, position, c)?))
+            }
+            '=' | '!' | '<' | '>' | '~' | '&' | '|' => {
+                chars.next();
+                let next = chars.peek().map(|(_, next)| *next);
+                let (token, double) = match (c, next) {
+                    ('=', Some('=')) => (Token::Op(Op::Eq), true),
+                    ('=', _) => (Token::Op(Op::Eq), false),
+                    ('!', Some('=')) => (Token::Op(Op::Ne), true),
+                    ('!', _) => (Token::Not, false),
+                    ('<', Some('=')) => (Token::Op(Op::Le), true),
+                    ('<', _) => (Token::Op(Op::Lt), false),
+                    ('>', Some('=')) => (Token::Op(Op::Ge), true),
+                    ('>', _) => (Token::Op(Op::Gt), false),
+                    ('~', _) => (Token::Op(Op::Match), false),
+                    ('&', Some('&')) => (Token::And, true),
+                    ('|', Some('|')) => (Token::Or, true),
+                    _ => return Err(error(text, position, format!("unexpected {c:?}"))),
+                };
+                if double {
+                    chars.next();
+                }
+                token
+            }
+            c if c.is_ascii_digit() && date(&text[position..]).is_some() => {
+                let date = date(&text[position..]).unwrap_or_default();
+                // Dates are ASCII, as many characters as bytes.
+                for _ in 0..date.len() {
+                    chars.next();
+                }
+                let seconds = timestamp(date).ok_or_else(|| {
+                    error(
+                        text,
+                        position,
+                        format!("invalid date {date:?}, expected YYYY-MM-DD or RFC 3339"),
+                    )
+                })?;
+                Token::Literal(Value::Number(seconds))
+            }
+            c if c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_digit() || *c == '.') {
+                    number.push(c);
+                }
+                let value = number
+                    .parse::<f64>()
+                    .map_err(|_| error(text, position, format!("invalid number {number:?}")))?;
+
+                let suffix = word(&mut chars);
+                if suffix.is_empty() {
+                    Token::Literal(Value::Number(value))
+                } else {
+                    let seconds = unit(&suffix).ok_or_else(|| {
+                        error(
+                            text,
+                            position + number.len(),
+                            format!("unknown unit {suffix:?}, expected s, m, h, d or w"),
+                        )
+                    })?;
+                    Token::Literal(Value::Number(value * seconds))
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let word = word(&mut chars);
+                match word.to_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    "true" => Token::Literal(Value::Bool(true)),
+                    "false" => Token::Literal(Value::Bool(false)),
+                    "tag" if chars.next_if(|(_, c)| *c == ':').is_some() => {
+                        let key = match chars.next_if(|(_, c)| matches!(c, '"' | '\'')) {
+                            Some((position, quote)) => quoted(&mut chars, position, quote)?,
+                            None => word_until_space(&mut chars),
+                        };
+                        if key.is_empty() {
+                            return Err(error(
+                                text,
+                                position + word.len() + 1,
+                                "expected a tag key".to_owned(),
+                            ));
+                        }
+                        Token::Tag(key)
+                    }
+                    _ if ATTRIBUTES.contains(&word.as_str()) => Token::Identifier(word),
+                    _ => Token::Word(word),
+                }
+            }
+            c => return Err(error(text, position, format!("unexpected {c:?}"))),
+        };
+        tokens.push((position, token));
+    }
+
+    Ok(tokens)
+}
+
+/// Reads a tag key up to the next space, parenthesis or operator.
+fn word_until_space(chars: &mut std::iter::Peekable<std::str::CharIndices>) -> String {
+    let mut word = String::new();
+    while let Some((_, c)) = chars.next_if(|(_, c)| {
+        !c.is_whitespace() && !matches!(c, '(' | ')' | '=' | '!' | '<' | '>' | '~' | '&' | '|')
+    }) {
+        word.push(c);
+    }
+    word
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    tokens: &'a [(usize, Token)],
+    index: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, position: usize, reason: String) -> Error {
+        error(self.text, position, reason)
+    }
+
+    /// Position of the current token, end of text once exhausted.
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.index)
+            .map_or(self.text.len(), |(position, _)| *position)
+    }
+
+    fn next_if(&mut self, token: &Token) -> bool {
+        let matches = self.tokens.get(self.index).map(|(_, next)| next) == Some(token);
+        if matches {
+            self.index += 1;
+        }
+        matches
+    }
+
+    fn or(&mut self) -> Result<Node> {
+        let mut node = self.and()?;
+        while self.next_if(&Token::Or) {
+            node = Node::Or(Box::new(node), Box::new(self.and()?));
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node> {
+        let mut node = self.not()?;
+        while self.next_if(&Token::And) {
+            node = Node::And(Box::new(node), Box::new(self.not()?));
+        }
+        Ok(node)
+    }
+
+    fn not(&mut self) -> Result<Node> {
+        if self.next_if(&Token::Not) {
+            Ok(Node::Not(Box::new(self.not()?)))
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Node> {
+        if self.next_if(&Token::Open) {
+            let node = self.or()?;
+            if !self.next_if(&Token::Close) {
+                return Err(self.error(self.position(), "expected ')'".to_owned()));
+            }
+            return Ok(node);
+        }
+
+        let left = self.operand(false)?;
+        let Some((_, Token::Op(op))) = self.tokens.get(self.index) else {
+            return Ok(Node::Truthy(left));
+        };
+        let op = *op;
+        self.index += 1;
+
+        let position = self.position();
+        let right = self.operand(true)?;
+        if op == Op::Match {
+            let Operand::Literal(Value::Text(pattern)) = right else {
+                return Err(self.error(position, "expected a regex".to_owned()));
+            };
+            let regex = regex::Regex::new(&pattern)
+                .map_err(|_| self.error(position, format!("invalid regex {pattern:?}")))?;
+            Ok(Node::Match(left, regex))
+        } else {
+            Ok(Node::Compare(left, op, right))
+        }
+    }
+
+    /// Unquoted words are values on the right hand side, unknown attributes otherwise.
+    fn operand(&mut self, right: bool) -> Result<Operand> {
+        let position = self.position();
+        let operand = match self.tokens.get(self.index) {
+            Some((_, Token::Identifier(name))) => Operand::Attribute(name.clone()),
+            Some((_, Token::Word(word))) if right => Operand::Literal(Value::Text(word.clone())),
+            Some((_, Token::Word(word))) => {
+                return Err(self.error(
+                    position,
+                    format!(
+                        "unknown attribute {word:?}, expected tag:KEY or one of {}",
+                        ATTRIBUTES.join(", ")
+                    ),
+                ))
+            }
+            Some((_, Token::Tag(key))) => Operand::Tag(key.clone()),
+            Some((_, Token::Literal(value))) => Operand::Literal(value.clone()),
+            Some((_, token)) => {
+                return Err(self.error(
+                    position,
+                    format!("expected an attribute, tag or value, found {token}"),
+                ))
+            }
+            None => {
+                return Err(self.error(position, "expected an attribute, tag or value".to_owned()))
+            }
+        };
+        self.index += 1;
+        Ok(operand)
+    }
+}
+
+impl Operand {
+    fn value(&self, resource: &impl Resource) -> Option<Value> {
+        match self {
+            Self::Attribute(name) => resource.attribute(name),
+            Self::Tag(key) => resource.tag(key).map(|value| Value::Text(value.to_owned())),
+            Self::Literal(value) => Some(value.clone()),
+        }
+    }
+}
+
+impl Node {
+    fn evaluate(&self, resource: &impl Resource) -> bool {
+        match self {
+            Self::And(left, right) => left.evaluate(resource) && right.evaluate(resource),
+            Self::Or(left, right) => left.evaluate(resource) || right.evaluate(resource),
+            Self::Not(node) => !node.evaluate(resource),
+            Self::Truthy(operand) => match operand.value(resource) {
+                Some(Value::Bool(value)) => value,
+                value => value.is_some(),
+            },
+            Self::Match(operand, regex) => match operand.value(resource) {
+                Some(Value::Text(value)) => regex.is_match(&value),
+                _ => false,
+            },
+            // Missing attributes and mismatched types compare false.
+            Self::Compare(left, op, right) => {
+                let ordering = match (left.value(resource), right.value(resource)) {
+                    (Some(Value::Number(left)), Some(Value::Number(right))) => {
+                        left.partial_cmp(&right)
+                    }
+                    (Some(Value::Text(left)), Some(Value::Text(right))) => Some(left.cmp(&right)),
+                    (Some(Value::Bool(left)), Some(Value::Bool(right))) => Some(left.cmp(&right)),
+                    _ => None,
+                };
+                ordering.is_some_and(|ordering| match op {
+                    Op::Eq => ordering == Ordering::Equal,
+                    Op::Ne => ordering != Ordering::Equal,
+                    Op::Lt => ordering == Ordering::Less,
+                    Op::Le => ordering != Ordering::Greater,
+                    Op::Gt => ordering == Ordering::Greater,
+                    Op::Ge => ordering != Ordering::Less,
+                    Op::Match => false,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    struct Fake {
+        attributes: BTreeMap<&'static str, Value>,
+        tags: BTreeMap<&'static str, &'static str>,
+    }
+
+    impl Resource for Fake {
+        fn id(&self) -> &str {
+            "vol-1"
+        }
+
+        fn name(&self) -> &str {
+            "data"
+        }
+
+        fn tag(&self, key: &str) -> Option<&str> {
+            self.tags.get(key).copied()
+        }
+
+        fn attribute(&self, name: &str) -> Option<Value> {
+            self.attributes.get(name).cloned()
+        }
+    }
+
+    /// Unencrypted 150 GiB volume created 2023-06-01, 40 days old, owned by data.
+    fn fake() -> Fake {
+        Fake {
+            attributes: BTreeMap::from([
+                ("age", Value::Number(40.0 * 86400.0)),
+                ("created", Value::Number(1_685_577_600.0)),
+                ("encrypted", Value::Bool(false)),
+                ("size", Value::Number(150.0)),
+                ("type", Value::Text("gp2".to_owned())),
+            ]),
+            tags: BTreeMap::from([("Owner", "data")]),
+        }
+    }
+
+    fn matches(expression: &str) -> bool {
+        Expression::parse(expression).unwrap().matches(&fake())
+    }
+
+    /// Position and reason of the parse error.
+    fn invalid(expression: &str) -> (usize, String) {
+        match Expression::parse(expression) {
+            Err(Error::InvalidExpression {
+                position, reason, ..
+            }) => (position, reason),
+            Err(error) => panic!("unexpected error {error}"),
+            Ok(_) => panic!("{expression:?} parsed"),
+        }
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert!(matches(
+            "encrypted = true OR size > 100 AND tag:Owner = data"
+        ));
+        assert!(!matches(
+            "(encrypted = true OR size > 100) AND tag:Owner = ops"
+        ));
+        assert!(matches("size > 100 && type = gp2 || encrypted"));
+    }
+
+    #[test]
+    fn not_negates_presence_and_booleans() {
+        assert!(matches("NOT encrypted"));
+        assert!(!matches("NOT tag:Owner"));
+        assert!(matches("!tag:Keep AND NOT NOT tag:Owner"));
+        assert!(!matches("not (size > 100)"));
+    }
+
+    #[test]
+    fn durations_compare_in_seconds() {
+        assert!(matches("age > 30d"));
+        assert!(matches("age < 6w"));
+        assert!(matches("age >= 960h AND age <= 57600m"));
+    }
+
+    #[test]
+    fn dates_compare_as_timestamps() {
+        assert!(matches("created < 2024-01-01"));
+        assert!(matches("created = 2023-06-01"));
+        assert!(!matches("created >= 2023-06-01T00:00:01Z"));
+        assert!(matches("(created > 2023-01-01)"));
+    }
+
+    #[test]
+    fn missing_attributes_compare_false() {
+        assert!(!matches("iops > 0"));
+        assert!(!matches("iops <= 0"));
+        assert!(!matches("size = gp2"));
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        assert_eq!(invalid("age > 30x").0, 8);
+        assert_eq!(invalid("size >").0, 6);
+        assert_eq!(invalid("(size > 1").0, 9);
+        assert_eq!(invalid("size > 1 size").0, 9);
+        assert!(invalid("owner = me").1.starts_with("unknown attribute"));
+        assert!(invalid("created < 2024-13-01")
+            .1
+            .starts_with("invalid date"));
+        assert_eq!(invalid("name ~ '('").1, "invalid regex \"(\"");
+        assert_eq!(invalid("tag:Owner = 'data").1, "unterminated quote");
+    }
+}
@@ -0,0 +1,363 @@
+use crate::{
+    error::{regex, Error, Result},
+    expression::Expression,
+    options::Kind,
+};
+use aws_sdk_ec2::model::Filter;
+use std::{iter::Peekable, str::CharIndices};
+
+/// Attribute value, sizes in GiB, ages in seconds and dates in seconds since the epoch.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Number(f64),
+    Text(String),
+}
+
+/// Attributes filter terms and expressions apply to.
+pub trait Resource {
+    fn id(&self) -> &str;
+    /// Image name, `Name` tag of snapshots and volumes.
+    fn name(&self) -> &str;
+    fn tag(&self, key: &str) -> Option<&str>;
+    /// One of `expression::ATTRIBUTES`, `None` when missing or irrelevant to the kind.
+    fn attribute(&self, name: &str) -> Option<Value>;
+}
+
+/// Seconds elapsed since the date.
+pub fn age(secs: i64) -> Value {
+    Value::Number((chrono::Utc::now().timestamp() - secs) as f64)
+}
+
+enum Field {
+    Id,
+    Name,
+    Tag(String),
+}
+
+enum Matcher {
+    /// EC2 wildcards: `*` matches zero or more characters, `?` zero or one.
+    Glob {
+        pattern: String,
+        regex: regex::Regex,
+    },
+    Regex(regex::Regex),
+    /// Tag presence.
+    Exists,
+}
+
+/// One filter, such as `tag:Team=data*`, `name~^app-` or `!tag:Keep`.
+pub struct Term {
+    negated: bool,
+    field: Field,
+    matcher: Matcher,
+}
+
+/// Filters and expression every resource must match.
+#[derive(Default)]
+pub struct Selection {
+    terms: Vec<Term>,
+    expression: Option<Expression>,
+}
+
+impl Selection {
+    pub fn parse(filters: Option<&[String]>, expression: Option<&str>) -> Result<Self> {
+        Ok(Self {
+            terms: filters
+                .unwrap_or_default()
+                .iter()
+                .map(|filter| Term::parse(filter))
+                .collect::<Result<_>>()?,
+            expression: expression.map(Expression::parse).transpose()?,
+        })
+    }
+
+    /// EC2 filters narrowing the search server side, everything is still checked locally.
+    pub fn server(&self, kind: Kind) -> Vec<Filter> {
+        self.terms
+            .iter()
+            .filter_map(|term| term.server(kind))
+            .collect()
+    }
+
+    pub fn matches(&self, resource: &impl Resource) -> bool {
+        self.terms.iter().all(|term| term.matches(resource))
+            && self
+                .expression
+                .as_ref()
+                .is_none_or(|expression| expression.matches(resource))
+    }
+}
+
+impl Term {
+    fn parse(filter: &str) -> Result<Self> {
+        let error = |position, reason| Error::InvalidFilter {
+            filter: filter.to_owned(),
+            position,
+            reason,
+        };
+
+        let mut chars = filter.char_indices().peekable();
+        let negated = chars.next_if(|(_, c)| *c == '!').is_some();
+
+        let start = chars.peek().map_or(filter.len(), |(index, _)| *index);
+        let word = read(&mut chars, |c| matches!(c, ':' | '=' | '~'));
+        let field = match word.as_str() {
+            "id" => Field::Id,
+            "name" => Field::Name,
+            "tag" => {
+                if chars.next_if(|(_, c)| *c == ':').is_none() {
+                    return Err(error(start + word.len(), "expected ':' after tag"));
+                }
+                let position = chars.peek().map_or(filter.len(), |(index, _)| *index);
+                let key = string(&mut chars, filter, |c| matches!(c, '=' | '~'))?;
+                if key.is_empty() {
+                    return Err(error(position, "expected a tag key"));
+                }
+                Field::Tag(key)
+            }
+            _ => return Err(error(start, "expected id, name or tag:KEY")),
+        };
+
+        let matcher = match chars.next() {
+            None if matches!(field, Field::Tag(_)) => Matcher::Exists,
+            None => return Err(error(filter.len(), "expected '=' or '~'")),
+            Some((_, '=')) => {
+                let pattern = string(&mut chars, filter, |_| false)?;
+                Matcher::Glob {
+                    regex: regex(&glob(&pattern))?,
+                    pattern,
+                }
+            }
+            Some((_, '~')) => Matcher::Regex(regex(&string(&mut chars, filter, |_| false)?)?),
+            Some((position, _)) => return Err(error(position, "expected '=' or '~'")),
+        };
+
+        match chars.next() {
+            Some((position, _)) => Err(error(position, "unexpected text after value")),
+            None => Ok(Self {
+                negated,
+                field,
+                matcher,
+            }),
+        }
+    }
+
+    fn server(&self, kind: Kind) -> Option<Filter> {
+        if self.negated {
+            return None;
+        }
+
+        let (name, value) = match (&self.field, &self.matcher) {
+            (Field::Tag(key), Matcher::Exists) => ("tag-key".to_owned(), key.clone()),
+            (Field::Tag(key), Matcher::Glob { pattern, .. }) => {
+                (format!("tag:{key}"), pattern.clone())
+            }
+            (Field::Name, Matcher::Glob { pattern, .. }) => (
+                match kind {
+                    Kind::Image => "name",
+                    Kind::Snapshot | Kind::Volume => "tag:Name",
+                }
+                .to_owned(),
+                pattern.clone(),
+            ),
+            (Field::Id, Matcher::Glob { pattern, .. }) => (
+                match kind {
+                    Kind::Image => "image-id",
+                    Kind::Snapshot => "snapshot-id",
+                    Kind::Volume => "volume-id",
+                }
+                .to_owned(),
+                pattern.clone(),
+            ),
+            _ => return None,
+        };
+
+        Some(Filter::builder().name(name).values(value).build())
+    }
+
+    fn matches(&self, resource: &impl Resource) -> bool {
+        let value = match &self.field {
+            Field::Id => Some(resource.id()),
+            Field::Name => Some(resource.name()),
+            Field::Tag(key) => resource.tag(key),
+        };
+
+        let matches = match &self.matcher {
+            Matcher::Exists => value.is_some(),
+            Matcher::Glob { regex, .. } | Matcher::Regex(regex) => {
+                value.is_some_and(|value| regex.is_match(value))
+            }
+        };
+
+        matches != self.negated
+    }
+}
+
+/// Reads up to, not including, the first delimiter.
+fn read(chars: &mut Peekable<CharIndices>, delimiter: impl Fn(char) -> bool) -> String {
+    let mut word = String::new();
+    while let Some((_, c)) = chars.next_if(|(_, c)| !delimiter(*c)) {
+        word.push(c);
+    }
+    word
+}
+
+/// Reads a single or double quoted string, or up to the first delimiter.
+///
+/// Within quotes, a backslash only escapes the quote and itself, leaving wildcard escapes intact.
+fn string(
+    chars: &mut Peekable<CharIndices>,
+    filter: &str,
+    delimiter: impl Fn(char) -> bool,
+) -> Result<String> {
+    let Some((start, quote)) = chars.next_if(|(_, c)| matches!(c, '"' | '\'')) else {
+        return Ok(read(chars, delimiter));
+    };
+
+    let mut string = String::new();
+    while let Some((_, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next_if(|(_, next)| *next == quote || *next == '\\') {
+                Some((_, escaped)) => string.push(escaped),
+                None => string.push('\\'),
+            },
+            c if c == quote => return Ok(string),
+            c => string.push(c),
+        }
+    }
+
+    Err(Error::InvalidFilter {
+        filter: filter.to_owned(),
+        position: start,
+        reason: "unterminated quote",
+    })
+}
+
+/// Translates EC2 wildcards to an anchored regex, `\` escaping the next character.
+fn glob(pattern: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push_str(".?"),
+            '\\' => regex.push_str(&regex::escape(&chars.next().unwrap_or('\\').to_string())),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    struct Fake {
+        name: &'static str,
+        tags: BTreeMap<&'static str, &'static str>,
+    }
+
+    impl Resource for Fake {
+        fn id(&self) -> &str {
+            "ami-0123"
+        }
+
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn tag(&self, key: &str) -> Option<&str> {
+            self.tags.get(key).copied()
+        }
+
+        fn attribute(&self, _: &str) -> Option<Value> {
+            None
+        }
+    }
+
+    fn fake(name: &'static str) -> Fake {
+        Fake {
+            name,
+            tags: BTreeMap::from([("Team", "data eng"), ("Note", "it's \"fine\"")]),
+        }
+    }
+
+    fn matches(filter: &str, name: &'static str) -> bool {
+        Term::parse(filter).unwrap().matches(&fake(name))
+    }
+
+    /// Position and reason of the parse error.
+    fn invalid(filter: &str) -> (usize, &'static str) {
+        match Term::parse(filter) {
+            Err(Error::InvalidFilter {
+                position, reason, ..
+            }) => (position, reason),
+            Err(error) => panic!("unexpected error {error}"),
+            Ok(_) => panic!("{filter:?} parsed"),
+        }
+    }
+
+    #[test]
+    fn globs_are_anchored_wildcards() {
+        assert!(matches("name=app-*", "app-1"));
+        assert!(!matches("name=app-*", "my-app-1"));
+        assert!(matches("name=app-?", "app-"));
+        assert!(!matches("name=app-?", "app-12"));
+        assert!(matches("name=a.b", "a.b"));
+        assert!(!matches("name=a.b", "axb"));
+    }
+
+    #[test]
+    fn quotes_allow_delimiters_and_keep_wildcards() {
+        assert!(matches("tag:Team='data eng'", "app"));
+        assert!(matches("tag:Team=\"data*\"", "app"));
+        assert!(matches("name='a=b*'", "a=b-1"));
+        assert!(matches("tag:'Team'~^data", "app"));
+    }
+
+    #[test]
+    fn backslashes_escape_quotes_and_wildcards() {
+        assert!(matches(r#"tag:Note='it\'s "fine"'"#, "app"));
+        assert!(matches(r#"tag:Note="it's \"fine\"""#, "app"));
+        assert!(matches(r"name='app\*'", "app*"));
+        assert!(!matches(r"name='app\*'", "app-1"));
+        assert!(matches(r"name=app\?", "app?"));
+        // Quotes unescape once, then the glob does.
+        assert!(matches(r"name=a\\b", r"a\b"));
+        assert!(matches(r"name='a\\\\b'", r"a\b"));
+        assert!(matches(r"name='a\\b'", "ab"));
+    }
+
+    #[test]
+    fn negation_and_presence() {
+        assert!(matches("tag:Team", "app"));
+        assert!(!matches("!tag:Team", "app"));
+        assert!(matches("!tag:Keep", "app"));
+        assert!(matches("!name~^base-", "app"));
+    }
+
+    #[test]
+    fn server_filters_skip_negations_and_regexes() {
+        let server = |filter| Term::parse(filter).unwrap().server(Kind::Snapshot);
+        let filter = server("name=db-*").unwrap();
+        assert_eq!(filter.name(), Some("tag:Name"));
+        assert_eq!(filter.values(), Some(&["db-*".to_owned()][..]));
+        assert!(server("!name=db-*").is_none());
+        assert!(server("name~^db-").is_none());
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_character() {
+        assert_eq!(invalid("tag:Team='data"), (9, "unterminated quote"));
+        assert_eq!(invalid(r#"name="app\""#), (5, "unterminated quote"));
+        assert_eq!(invalid("owner=me"), (0, "expected id, name or tag:KEY"));
+        assert_eq!(invalid("!owner=me"), (1, "expected id, name or tag:KEY"));
+        assert_eq!(invalid("tag=x"), (3, "expected ':' after tag"));
+        assert_eq!(invalid("tag:=x"), (4, "expected a tag key"));
+        assert_eq!(invalid("name"), (4, "expected '=' or '~'"));
+        assert_eq!(invalid("name='a'b"), (8, "unexpected text after value"));
+    }
+}
@@ -0,0 +1,96 @@
+use crate::{
+    aws::lineage::{Kind, Lineage, Node},
+    options::GraphFormat,
+};
+use std::collections::HashSet;
+
+pub fn render(lineage: &Lineage, format: GraphFormat, planned: &HashSet<String>) -> String {
+    match format {
+        GraphFormat::Dot => dot(lineage, planned),
+        GraphFormat::Mermaid => mermaid(lineage, planned),
+    }
+}
+
+/// Kind and id, then name and size if any, one per line.
+fn label(node: &Node, newline: &str) -> String {
+    let mut label = format!("{} {}", node.kind, node.id);
+    if !node.name.is_empty() {
+        label += newline;
+        label += &node.name;
+    }
+    if node.size > 0 {
+        label += &format!("{newline}{} GiB", node.size);
+    }
+    label
+}
+
+fn dot(lineage: &Lineage, planned: &HashSet<String>) -> String {
+    let quote = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+
+    let mut dot =
+        "digraph lineage {\n  rankdir=LR;\n  node [fontname=\"sans-serif\"];\n".to_owned();
+    for node in &lineage.nodes {
+        let shape = match node.kind {
+            Kind::Image => "box",
+            Kind::Snapshot => "note",
+            Kind::Volume => "cylinder",
+            Kind::Instance => "component",
+        };
+        let style = if planned.contains(&node.id) {
+            ", style=filled, fillcolor=\"#f8d0d0\", color=\"#c00000\""
+        } else {
+            ""
+        };
+        dot += &format!(
+            "  {} [label={}, shape={shape}{style}];\n",
+            quote(&node.id),
+            quote(&label(node, "\n")).replace('\n', "\\n")
+        );
+    }
+    for edge in &lineage.edges {
+        dot += &format!(
+            "  {} -> {} [label={}];\n",
+            quote(&edge.from),
+            quote(&edge.to),
+            quote(&edge.relation.to_string())
+        );
+    }
+    dot + "}\n"
+}
+
+fn mermaid(lineage: &Lineage, planned: &HashSet<String>) -> String {
+    // Identifiers are restricted, labels carry the actual IDs.
+    let id = |id: &str| id.replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+
+    let mut mermaid = "flowchart LR\n".to_owned();
+    for node in &lineage.nodes {
+        let label = format!("\"{}\"", label(node, "<br/>").replace('"', "#quot;"));
+        let shape = match node.kind {
+            Kind::Image => format!("[{label}]"),
+            Kind::Snapshot => format!("([{label}])"),
+            Kind::Volume => format!("[({label})]"),
+            Kind::Instance => format!("{{{{{label}}}}}"),
+        };
+        mermaid += &format!("  {}{shape}\n", id(&node.id));
+    }
+    for edge in &lineage.edges {
+        mermaid += &format!(
+            "  {} -->|{}| {}\n",
+            id(&edge.from),
+            edge.relation,
+            id(&edge.to)
+        );
+    }
+
+    let planned = lineage
+        .nodes
+        .iter()
+        .filter(|node| planned.contains(&node.id))
+        .map(|node| id(&node.id))
+        .collect::<Vec<_>>();
+    if !planned.is_empty() {
+        mermaid += "  classDef planned fill:#f8d0d0,stroke:#c00000\n";
+        mermaid += &format!("  class {} planned\n", planned.join(","));
+    }
+    mermaid
+}
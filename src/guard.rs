@@ -0,0 +1,138 @@
+use crate::{
+    error::{Error, Result},
+    options::Guards,
+    out::Out,
+    retention::Rule,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Resources the plan was selected from.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Inventory {
+    /// Candidates matching the selection the plan was made from, 0 when unknown.
+    pub total: usize,
+
+    /// Images left per retention group once the plan is applied, for groups the plan touches, under
+    /// an empty name when images are not grouped.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub survivors: BTreeMap<String, usize>,
+
+    /// Resources kept by a retention schedule, with the rule keeping them.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub retained: BTreeMap<String, Rule>,
+}
+
+/// Lists every limit the plan exceeds.
+pub fn violations(out: &Out, guards: &Guards) -> Vec<String> {
+    let mut violations = vec![];
+
+    if let Some(max) = guards.max_count {
+        let count = out.count();
+        if count > max {
+            violations.push(format!(
+                "{count} resources would be deleted, more than --max-count {max}"
+            ));
+        }
+    }
+
+    if let Some(max) = guards.max_size {
+        let size = out.size();
+        if size > max {
+            violations.push(format!(
+                "{size} GiB would be deleted, more than --max-size {max}"
+            ));
+        }
+    }
+
+    if let Some(max) = guards.max_percent {
+        if out.inventory.total == 0 {
+            violations.push("Inventory is unknown, --max-percent cannot be checked".to_owned());
+        } else {
+            let percent = 100.0 * out.len() as f64 / out.inventory.total as f64;
+            if percent > max {
+                violations.push(format!(
+                    "{percent:.1}% of {} matched resources would be deleted, more than --max-percent {max}",
+                    out.inventory.total
+                ));
+            }
+        }
+    }
+
+    if let Some(min) = guards.min_survivors {
+        if out.inventory.survivors.is_empty() {
+            violations.push("Image groups are unknown, --min-survivors cannot be checked".to_owned());
+        }
+        for (group, survivors) in &out.inventory.survivors {
+            if *survivors < min {
+                let group = if group.is_empty() {
+                    "in total".to_owned()
+                } else {
+                    format!("in group {group:?}")
+                };
+                violations.push(format!(
+                    "{survivors} images would remain {group}, less than --min-survivors {min}"
+                ));
+            }
+        }
+    }
+
+    violations
+}
+
+/// Aborts when the plan exceeds a limit, unless forced to proceed.
+pub fn check(out: &Out, guards: &Guards) -> Result<()> {
+    let violations = violations(out, guards);
+
+    if violations.is_empty() {
+        Ok(())
+    } else if guards.force {
+        for violation in &violations {
+            log::warn!("Forced past safety limit: {violation}");
+        }
+        Ok(())
+    } else {
+        Err(Error::Unsafe(violations))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn guards(min_survivors: usize) -> Guards {
+        Guards {
+            max_count: None,
+            max_size: None,
+            max_percent: None,
+            min_survivors: Some(min_survivors),
+            force: false,
+        }
+    }
+
+    fn plan(inventory: serde_json::Value) -> Out {
+        serde_json::from_value(json!({
+            "Images": [{"id": "ami-1", "name": "app-1", "creation_date": "", "snapshots": []}],
+            "inventory": inventory,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn min_survivors_needs_known_groups() {
+        assert_eq!(
+            violations(&plan(json!({"total": 1})), &guards(1)),
+            ["Image groups are unknown, --min-survivors cannot be checked"]
+        );
+    }
+
+    #[test]
+    fn ungrouped_survivors_count_in_total() {
+        let out = plan(json!({"total": 1, "survivors": {"": 0, "app": 2}}));
+        assert_eq!(
+            violations(&out, &guards(1)),
+            ["0 images would remain in total, less than --min-survivors 1"]
+        );
+    }
+}
@@ -0,0 +1,347 @@
+use crate::{
+    aws::{
+        lineage::{self, Edge, Lineage, Node, Relation},
+        volume,
+    },
+    options::Kind,
+    report::{self, html},
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// How the resource relates to another, seen from either end.
+fn relation(relation: Relation, outgoing: bool) -> &'static str {
+    match (relation, outgoing) {
+        (Relation::Backs, true) => "backed by",
+        (Relation::Backs, false) => "backs",
+        (Relation::Restores, true) => "restored as",
+        (Relation::Restores, false) => "restored from",
+        (Relation::Launches, true) => "launched",
+        (Relation::Launches, false) => "launched from",
+        (Relation::Attaches, true) => "attached to",
+        (Relation::Attaches, false) => "holds",
+    }
+}
+
+#[derive(Serialize)]
+pub struct Item {
+    kind: String,
+    id: String,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    age_days: Option<i64>,
+    /// In GiB, 0 for images, which hold their snapshots.
+    size: i64,
+    /// Estimated, in USD per month.
+    cost: f64,
+    used: bool,
+    /// What uses the resource, or why nothing does.
+    usage: String,
+    /// Related resources, such as `backed by snap-0123`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    related: Vec<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    tags: BTreeMap<String, String>,
+}
+
+#[derive(Default, Serialize)]
+pub struct Totals {
+    resources: usize,
+    unused: usize,
+    size: i64,
+    cost: f64,
+    /// Cost of the unused resources.
+    waste: f64,
+}
+
+impl Totals {
+    fn add(&mut self, item: &Item) {
+        self.resources += 1;
+        self.size += item.size;
+        self.cost += item.cost;
+        if !item.used {
+            self.unused += 1;
+            self.waste += item.cost;
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Group {
+    /// Values of the grouping tags, `-` when missing.
+    name: String,
+    totals: Totals,
+    items: Vec<Item>,
+}
+
+/// Self-owned images, snapshots and volumes, used or not, grouped by tags.
+#[derive(Serialize)]
+pub struct Inventory {
+    groups: Vec<Group>,
+    totals: Totals,
+}
+
+impl Inventory {
+    /// Judges usage from the relations, images launched and snapshots backing a used image, and
+    /// from the volumes themselves, used unless available.
+    pub fn new(lineage: &Lineage, group_by: &[String]) -> Self {
+        let edges = |id: &str, outgoing: bool| {
+            lineage
+                .edges
+                .iter()
+                .filter(move |edge| {
+                    if outgoing {
+                        edge.from == id
+                    } else {
+                        edge.to == id
+                    }
+                })
+                .collect::<Vec<&Edge>>()
+        };
+        let launched = |id: &str| {
+            edges(id, true)
+                .iter()
+                .filter(|edge| edge.relation == Relation::Launches)
+                .map(|edge| edge.to.as_str())
+                .collect::<Vec<_>>()
+        };
+
+        let mut groups = BTreeMap::<String, Vec<Item>>::new();
+        for node in &lineage.nodes {
+            // Instances only matter through the resources they use.
+            let kind = match node.kind {
+                lineage::Kind::Image => Kind::Image,
+                lineage::Kind::Snapshot => Kind::Snapshot,
+                lineage::Kind::Volume => Kind::Volume,
+                lineage::Kind::Instance => continue,
+            };
+
+            let (used, usage) = match kind {
+                Kind::Image => {
+                    let instances = launched(&node.id);
+                    (
+                        !instances.is_empty(),
+                        if instances.is_empty() {
+                            "no instance launched".to_owned()
+                        } else {
+                            format!("launched {}", instances.join(","))
+                        },
+                    )
+                }
+                Kind::Snapshot => {
+                    let images = edges(&node.id, false)
+                        .into_iter()
+                        .filter(|edge| edge.relation == Relation::Backs)
+                        .map(|edge| edge.from.as_str())
+                        .collect::<Vec<_>>();
+                    let used = images
+                        .iter()
+                        .filter(|image| !launched(image).is_empty())
+                        .copied()
+                        .collect::<Vec<_>>();
+                    if !used.is_empty() {
+                        (true, format!("backs used {}", used.join(",")))
+                    } else if !images.is_empty() {
+                        (false, format!("backs unused {}", images.join(",")))
+                    } else {
+                        (false, "backs no image".to_owned())
+                    }
+                }
+                // Straight from the volume, attaching or detaching ones being in use too.
+                Kind::Volume => match (
+                    node.details.get("attached"),
+                    node.details.get("state").map(String::as_str),
+                ) {
+                    (Some(instances), _) => (true, format!("attached to {instances}")),
+                    (None, Some(volume::AVAILABLE)) => (false, "not attached".to_owned()),
+                    (None, state) => (true, state.unwrap_or("unknown state").to_owned()),
+                },
+            };
+
+            let related = edges(&node.id, true)
+                .into_iter()
+                .map(|edge| format!("{} {}", relation(edge.relation, true), edge.to))
+                .chain(
+                    edges(&node.id, false)
+                        .into_iter()
+                        .map(|edge| format!("{} {}", relation(edge.relation, false), edge.from)),
+                )
+                .collect();
+
+            let group = if group_by.is_empty() {
+                "all".to_owned()
+            } else {
+                group_by
+                    .iter()
+                    .map(|key| format!("{key}={}", node.tags.get(key).map_or("-", String::as_str)))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+
+            groups.entry(group).or_default().push(Item {
+                kind: kind.to_string(),
+                id: node.id.clone(),
+                name: node.name.clone(),
+                date: node.date.clone(),
+                age_days: age(node),
+                size: node.size,
+                cost: node.size as f64 * report::price(kind, &node.details),
+                used,
+                usage,
+                related,
+                tags: node.tags.clone(),
+            });
+        }
+
+        let mut totals = Totals::default();
+        let groups = groups
+            .into_iter()
+            .map(|(name, items)| {
+                let mut group = Totals::default();
+                for item in &items {
+                    group.add(item);
+                    totals.add(item);
+                }
+                Group {
+                    name,
+                    totals: group,
+                    items,
+                }
+            })
+            .collect();
+
+        Self { groups, totals }
+    }
+
+    /// Totals per group, then a table of the resources of each.
+    pub fn markdown(&self) -> String {
+        let cell = |text: &str| text.replace('|', "\\|");
+
+        let mut markdown = "# Inventory\n\n\
+             | Group | Resources | Unused | Size (GiB) | USD/month | Unused USD/month |\n\
+             | --- | ---: | ---: | ---: | ---: | ---: |\n"
+            .to_owned();
+        for (name, totals) in self
+            .groups
+            .iter()
+            .map(|group| (group.name.as_str(), &group.totals))
+            .chain(std::iter::once(("**Total**", &self.totals)))
+        {
+            markdown += &format!(
+                "| {} | {} | {} | {} | {:.2} | {:.2} |\n",
+                cell(name),
+                totals.resources,
+                totals.unused,
+                totals.size,
+                totals.cost,
+                totals.waste
+            );
+        }
+
+        for group in &self.groups {
+            markdown += &format!(
+                "\n## {}\n\n\
+                 | Kind | ID | Name | Age (days) | Size (GiB) | USD/month | Usage | Related |\n\
+                 | --- | --- | --- | ---: | ---: | ---: | --- | --- |\n",
+                group.name
+            );
+            for item in &group.items {
+                markdown += &format!(
+                    "| {} | `{}` | {} | {} | {} | {:.2} | {}{} | {} |\n",
+                    item.kind,
+                    item.id,
+                    cell(&item.name),
+                    item.age_days
+                        .map_or_else(|| "-".to_owned(), |days| days.to_string()),
+                    item.size,
+                    item.cost,
+                    if item.used { "" } else { "**unused**, " },
+                    cell(&item.usage),
+                    cell(&item.related.join(", "))
+                );
+            }
+        }
+
+        markdown
+    }
+
+    /// Standalone page with totals per group and a table of every resource, unused ones highlighted.
+    pub fn page(&self) -> String {
+        let mut summary = String::new();
+        for group in &self.groups {
+            summary += &format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.2}</td><td>{:.2}</td></tr>\n",
+                html(&group.name),
+                group.totals.resources,
+                group.totals.unused,
+                group.totals.size,
+                group.totals.cost,
+                group.totals.waste
+            );
+        }
+        let total = format!(
+            "<tr><th>Total</th><th>{}</th><th>{}</th><th>{}</th><th>{:.2}</th><th>{:.2}</th></tr>",
+            self.totals.resources,
+            self.totals.unused,
+            self.totals.size,
+            self.totals.cost,
+            self.totals.waste
+        );
+
+        let mut rows = String::new();
+        for group in &self.groups {
+            for item in &group.items {
+                rows += &format!(
+                    "<tr{}><td>{}</td><td>{}</td><td><code>{}</code></td><td>{}</td><td>{}</td><td>{}</td><td>{:.2}</td><td>{}</td><td>{}</td></tr>\n",
+                    if item.used { "" } else { " class=\"unused\"" },
+                    html(&group.name),
+                    item.kind,
+                    html(&item.id),
+                    html(&item.name),
+                    item.age_days.map_or_else(String::new, |days| days.to_string()),
+                    item.size,
+                    item.cost,
+                    html(&item.usage),
+                    html(&item.related.join(", "))
+                );
+            }
+        }
+
+        report::document(
+            "Inventory",
+            &format!(
+                r#"<table class="sortable">
+<thead><tr><th>Group</th><th>Resources</th><th>Unused</th><th>Size (GiB)</th><th>USD/month</th><th>Unused USD/month</th></tr></thead>
+<tbody>
+{summary}</tbody>
+<tfoot>{total}</tfoot>
+</table>
+<table class="sortable">
+<thead><tr><th>Group</th><th>Kind</th><th>ID</th><th>Name</th><th>Age (days)</th><th>Size (GiB)</th><th>USD/month</th><th>Usage</th><th>Related</th></tr></thead>
+<tbody>
+{rows}</tbody>
+</table>
+"#
+            ),
+        )
+    }
+}
+
+/// Whole days since the resource was created.
+fn age(node: &Node) -> Option<i64> {
+    let date = DateTime::parse_from_rfc3339(node.date.as_deref()?).ok()?;
+    Some((Utc::now() - date.with_timezone(&Utc)).num_days())
+}
+
+impl std::fmt::Display for Inventory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{}",
+            serde_json::to_string_pretty(&self).map_err(|_| std::fmt::Error)?
+        )
+    }
+}
@@ -0,0 +1,226 @@
+use crate::{
+    error::{self, Error, Result},
+    options::Kind,
+};
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Started,
+    Deleted,
+    Failed,
+    Skipped,
+}
+
+/// One state transition of a resource, a line of the journal.
+#[derive(Serialize, Deserialize)]
+struct Transition {
+    time: String,
+    kind: String,
+    id: String,
+    state: State,
+    /// Error of a failure, parent of a skipped resource.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    /// Digest of the plan applied, missing from older journals.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    digest: Option<String>,
+}
+
+/// What previous runs recorded.
+#[derive(Debug, Default, PartialEq)]
+struct Previous {
+    /// Resources deleted.
+    done: HashSet<String>,
+    /// Digests of the plans applied.
+    digests: HashSet<String>,
+}
+
+/// Reads the transitions of a journal, ignoring a truncated last line left by an interrupted run.
+fn parse(text: &str) -> Result<Previous> {
+    let mut previous = Previous::default();
+    let lines = text.lines().filter(|line| !line.trim().is_empty());
+    let mut lines = lines.peekable();
+    while let Some(line) = lines.next() {
+        let transition = match serde_json::from_str::<Transition>(line) {
+            Ok(transition) => transition,
+            Err(error) if lines.peek().is_none() => {
+                log::warn!("Ignoring the truncated last line of the journal: {error}");
+                break;
+            }
+            Err(error) => return Err(error.into()),
+        };
+        if transition.state == State::Deleted {
+            previous.done.insert(transition.id);
+        }
+        previous.digests.extend(transition.digest);
+    }
+    Ok(previous)
+}
+
+/// State transitions of the resources of an applied plan, saved next to it.
+///
+/// Without a path, nothing is recorded.
+#[derive(Default)]
+pub struct Journal {
+    path: Option<PathBuf>,
+    resume: bool,
+    file: Option<File>,
+    /// What previous runs recorded, when resuming.
+    previous: Previous,
+    /// Digest of the plan applied, recorded with every transition.
+    digest: Option<String>,
+}
+
+impl Journal {
+    /// Journal of the plan, loading what previous runs deleted when resuming.
+    ///
+    /// Otherwise, an existing journal is only replaced once the first resource is started.
+    pub fn new(plan: &Path, resume: bool) -> Result<Self> {
+        let mut path = plan.as_os_str().to_owned();
+        path.push(".journal");
+        let path = PathBuf::from(path);
+
+        let mut previous = Previous::default();
+        if resume {
+            match std::fs::read(&path) {
+                Ok(bytes) => {
+                    previous = parse(&String::from_utf8_lossy(&bytes))?;
+                    log::info!(
+                        "Resuming, {} resources already deleted",
+                        previous.done.len()
+                    );
+                }
+                Err(source) if source.kind() == std::io::ErrorKind::NotFound => {
+                    log::warn!("No journal at {path:?}, starting over");
+                }
+                Err(source) => return Err(Error::Io { path, source }),
+            }
+        }
+
+        Ok(Self {
+            path: Some(path),
+            resume,
+            file: None,
+            previous,
+            digest: None,
+        })
+    }
+
+    /// Ties the journal to the plan about to be applied, refusing to resume another one.
+    pub fn bind(&mut self, digest: String) -> Result<()> {
+        if let Some(path) = self.path.as_ref().filter(|_| self.resume) {
+            if self.previous.digests.is_empty() && !self.previous.done.is_empty() {
+                log::warn!("{path:?} records no plan digest, trusting it matches the plan");
+            } else if self
+                .previous
+                .digests
+                .iter()
+                .any(|previous| *previous != digest)
+            {
+                return Err(Error::StaleJournal(path.clone()));
+            }
+        }
+        self.digest = Some(digest);
+        Ok(())
+    }
+
+    /// Whether a previous run deleted the resource.
+    pub fn is_done(&self, id: &str) -> bool {
+        self.previous.done.contains(id)
+    }
+
+    /// Appends a transition, reporting failures rather than interrupting deletions.
+    pub fn record(&mut self, kind: Kind, id: &str, state: State, reason: Option<String>) {
+        if let Err(error) = self.append(Transition {
+            time: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            kind: kind.to_string(),
+            id: id.to_owned(),
+            state,
+            reason,
+            digest: self.digest.clone(),
+        }) {
+            error::report(&error);
+        }
+    }
+
+    fn append(&mut self, transition: Transition) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let io = |source| Error::Io {
+            path: path.clone(),
+            source,
+        };
+
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self.file.insert(
+                OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .append(self.resume)
+                    .truncate(!self.resume)
+                    .open(path)
+                    .map_err(io)?,
+            ),
+        };
+        // A single write, so that an interruption leaves at most the last line truncated.
+        let mut line = serde_json::to_vec(&transition)?;
+        line.push(b'\n');
+        file.write_all(&line).map_err(io)?;
+        file.sync_data().map_err(io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JOURNAL: &str = r#"{"time":"2024-01-01T00:00:00.000Z","kind":"image","id":"ami-1","state":"started","digest":"abc"}
+{"time":"2024-01-01T00:00:01.000Z","kind":"image","id":"ami-1","state":"deleted","digest":"abc"}
+{"time":"2024-01-01T00:00:01.000Z","kind":"snapshot","id":"snap-1","state":"failed","reason":"throttled","digest":"abc"}
+"#;
+
+    #[test]
+    fn loads_deleted_resources_and_digests() {
+        let previous = parse(JOURNAL).unwrap();
+        assert_eq!(previous.done, HashSet::from(["ami-1".to_owned()]));
+        assert_eq!(previous.digests, HashSet::from(["abc".to_owned()]));
+    }
+
+    #[test]
+    fn ignores_a_truncated_last_line() {
+        let truncated = format!("{JOURNAL}{{\"time\":\"2024-01-01T00:00:02.000Z\",\"kind\":\"snap");
+        assert_eq!(parse(&truncated).unwrap(), parse(JOURNAL).unwrap());
+    }
+
+    #[test]
+    fn rejects_corruption_before_the_last_line() {
+        let corrupted = format!("{{\"time\n{JOURNAL}");
+        assert!(matches!(parse(&corrupted), Err(Error::Serialization(_))));
+    }
+
+    #[test]
+    fn refuses_to_resume_another_plan() {
+        let mut journal = Journal {
+            path: Some(PathBuf::from("plan.json.journal")),
+            resume: true,
+            previous: parse(JOURNAL).unwrap(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            journal.bind("def".to_owned()),
+            Err(Error::StaleJournal(_))
+        ));
+        assert!(journal.bind("abc".to_owned()).is_ok());
+    }
+}
@@ -1,111 +1,864 @@
+// AWS SDK errors are large by design and only ever travel up to `main`.
+#![allow(clippy::result_large_err)]
+
 mod aws;
+mod diff;
 mod error;
+mod explain;
+mod expression;
+mod filter;
+mod graph;
+mod guard;
+mod inventory;
+mod journal;
 mod options;
 mod out;
+mod plan;
+mod report;
+mod retention;
+mod review;
+mod signature;
+mod status;
+mod tui;
 
 use clap::Parser;
-use error::Result;
-use options::{Command, Options, SubCommand};
+use diff::Diff;
+use error::{Error, Result};
+use explain::Trace;
+use filter::Selection;
+use journal::Journal;
+use options::{
+    Command, Guards, InventoryFormat, Kind, Options, Prompt, ReportFormat, Retention, Source,
+    SubCommand,
+};
+use retention::Schedule;
+use signature::Verified;
+use status::Status;
+use std::{collections::HashSet, path::PathBuf, process::ExitCode};
+use tui::Outcome;
 
 use crate::{
     aws::{
-        image::{Builder as ImagesBuilder, DescribeImages},
-        snapshot::{Builder as SnapshotsBuilder, DescribeSnapshots},
-        volume::{Builder as VolumesBuilder, DescribeVolumes},
+        execution,
+        image::{self, Builder as ImagesBuilder, DescribeImages, Grouping, Images, Versioning},
+        lineage::Lineage,
+        permission::{self, Policy},
+        preflight::{self, Samples},
+        snapshot::{self, Builder as SnapshotsBuilder, DescribeSnapshots},
+        volume::{self, Builder as VolumesBuilder, DescribeVolumes},
     },
-    out::Out,
+    guard::Inventory,
+    out::{All, Out, Resources},
 };
-use aws_sdk_ec2::Client;
+use aws_sdk_ec2::{Client, Region};
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    env_logger::init();
+async fn main() -> ExitCode {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
 
     let options: Options = Options::parse();
     log::info!("Options are: {options:?}");
 
+    let status = match run(options).await {
+        Ok(status) => status,
+        Err(error) => {
+            eprintln!("Error: {}", error.report());
+            error.status()
+        }
+    };
+
+    status.max(error::reported()).into()
+}
+
+async fn run(options: Options) -> Result<Status> {
     std::env::set_var("AWS_PROFILE", options.profile);
-    std::env::set_var("AWS_REGION", options.region);
+    std::env::set_var("AWS_REGION", &options.region);
 
     let client = Client::new(&aws_config::load_from_env().await);
 
+    // Region to dry run deletions in before applying, if any.
+    let preflight = (!options.no_preflight).then_some(options.region.as_str());
+
+    let print = match (options.columns.as_deref(), options.report) {
+        (Some(columns), _) => {
+            out::columns(columns)?;
+            Print::Table(columns)
+        }
+        (None, Some(format)) => Print::Report(format),
+        (None, None) => Print::Json,
+    };
+
     match options.command {
         Command::Volume(command) => {
-            if let Ok(builder) =
-                VolumesBuilder::new(&client, DescribeVolumes::names(command.names)).await
-            {
-                let out = Out::Volumes(builder.build().await);
-                if let Some(path) = command.output {
-                    out.write(path);
-                } else if command.apply {
-                    out.cleanup(&client).await;
-                } else {
-                    println!("{out}");
-                }
-            }
+            let out = volumes(&client, &command, &mut Trace::default()).await?;
+            let apply = command.apply.then(|| {
+                (
+                    &command.guards,
+                    command.execution.parallelism,
+                    Journal::default(),
+                    None,
+                )
+            });
+            handle(
+                &client,
+                out,
+                command.output,
+                apply,
+                &command.prompt,
+                preflight,
+                print,
+            )
+            .await
         }
         Command::Snapshot(command) => {
-            if let Ok(builder) =
-                SnapshotsBuilder::new(&client, DescribeSnapshots::names(command.names)).await
-            {
-                let out = Out::Snapshots(builder.build().await);
-                if let Some(path) = command.output {
-                    out.write(path);
-                } else if command.apply {
-                    out.cleanup(&client).await;
-                } else {
-                    println!("{out}");
-                }
-            }
+            let out = snapshots(&client, &command, &mut Trace::default()).await?;
+            let apply = command.apply.then(|| {
+                (
+                    &command.guards,
+                    command.execution.parallelism,
+                    Journal::default(),
+                    None,
+                )
+            });
+            handle(
+                &client,
+                out,
+                command.output,
+                apply,
+                &command.prompt,
+                preflight,
+                print,
+            )
+            .await
         }
         Command::Image(command) => {
-            if let Ok(builder) = ImagesBuilder::new(
+            let (images, inventory) = images(&client, &command, &mut Trace::default()).await?;
+            let out = Out::new(Resources::Images(images), inventory);
+            let apply = command.apply.then(|| {
+                (
+                    &command.guards,
+                    command.execution.parallelism,
+                    Journal::default(),
+                    None,
+                )
+            });
+            handle(
+                &client,
+                out,
+                command.output,
+                apply,
+                &command.prompt,
+                preflight,
+                print,
+            )
+            .await
+        }
+        Command::All(command) => {
+            let out = all(&client, &command, &mut Trace::default()).await?;
+            let apply = command.apply.then(|| {
+                (
+                    &command.guards,
+                    command.execution.parallelism,
+                    Journal::default(),
+                    None,
+                )
+            });
+            handle(
                 &client,
-                DescribeImages {
-                    names: command.names,
-                    tags: command.tags,
-                    ..Default::default()
-                },
+                out,
+                command.output,
+                apply,
+                &command.prompt,
+                preflight,
+                print,
             )
             .await
-            {
-                let builder = if let Some(names) = command.exclude_names {
-                    builder.exclude_names(names)
-                } else {
-                    builder
-                };
-
-                let builder = if let Some(tags) = command.exclude_tags {
-                    builder.exclude_tags(tags)
-                } else {
-                    builder
-                };
-
-                let builder = match command.subcommand {
-                    SubCommand::Keep(keep) => builder.keep(keep.keep),
-                    SubCommand::Before(before) => builder.before(before.into()),
-                };
-
-                let out = Out::Images(builder.build().await?);
-
-                if let Some(path) = command.output {
-                    out.write(path);
-                } else if command.apply {
-                    out.cleanup(&client).await;
-                } else {
-                    println!("{out}");
+        }
+        Command::Read(read) => {
+            // Review decisions are saved back to the plan, then applied if asked.
+            let output = read.prompt.review.then(|| read.path.clone());
+            let out = Out::read(read.path.clone())?;
+            let verified = if read.apply {
+                Some(signature::verify(
+                    &out,
+                    &read.path,
+                    read.trusted_key.as_deref().unwrap_or_default(),
+                    read.allow_unsigned,
+                )?)
+            } else {
+                None
+            };
+            let apply = if read.apply {
+                Some((
+                    &read.guards,
+                    read.execution.parallelism,
+                    Journal::new(&read.path, read.resume)?,
+                    verified.as_ref(),
+                ))
+            } else {
+                None
+            };
+            handle(&client, out, output, apply, &read.prompt, preflight, print).await
+        }
+        Command::Sign(command) => {
+            let key = signature::sign(&command.path, &command.key)?;
+            println!("Signed {:?} with key {key}", command.path);
+            Ok(Status::Clean)
+        }
+        Command::Diff(command) => {
+            let diff = Diff::new(&Out::read(command.old)?, &Out::read(command.new)?);
+            print!("{}", diff.render(command.format));
+            if diff.is_empty() {
+                Ok(Status::Clean)
+            } else {
+                Ok(Status::Candidates)
+            }
+        }
+        Command::Plan(command) => plan::edit(command.command),
+        Command::Preflight(command) => {
+            let regions = command
+                .regions
+                .unwrap_or_else(|| vec![options.region.clone()]);
+
+            let actions = permission::union(&[
+                image::READ,
+                image::APPLY,
+                snapshot::READ,
+                snapshot::APPLY,
+                volume::READ,
+                volume::APPLY,
+            ]);
+
+            let mut status = Status::Clean;
+            for region in regions {
+                let config = aws_config::from_env()
+                    .region(Region::new(region.clone()))
+                    .load()
+                    .await;
+                let client = Client::new(&config);
+                let samples = Samples::describe(&client).await;
+
+                for check in preflight::check(&client, &region, &actions, &samples).await {
+                    println!("{check}");
+                    if check.is_denied() {
+                        status = Status::Unauthorized;
+                    }
                 }
+            }
+            Ok(status)
+        }
+        Command::Policy(command) => {
+            let kinds = command
+                .kinds
+                .unwrap_or_else(|| vec![Kind::Image, Kind::Snapshot, Kind::Volume]);
+            let tags = command
+                .tags
+                .unwrap_or_default()
+                .into_iter()
+                .map(|tag| match tag.split_once('=') {
+                    Some((key, value)) => Ok((key.to_owned(), value.to_owned())),
+                    None => Err(Error::InvalidTag(tag)),
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            print!("{}", Policy::new(&kinds, command.apply, tags));
+            Ok(Status::Clean)
+        }
+        Command::Lineage(command) => {
+            let planned = match command.plan {
+                Some(path) => out::pending(&Out::read(path)?.entries()),
+                None => HashSet::new(),
             };
+            let lineage = Lineage::describe(&client).await?;
+
+            let known = lineage
+                .nodes
+                .iter()
+                .map(|node| node.id.as_str())
+                .collect::<HashSet<_>>();
+            let missing = planned
+                .iter()
+                .filter(|id| !known.contains(id.as_str()))
+                .count();
+            if missing > 0 {
+                log::warn!("{missing} planned resources no longer exist");
+            }
+
+            print!("{}", graph::render(&lineage, command.format, &planned));
+            Ok(Status::Clean)
         }
-        Command::Read(read) => {
-            let out = Out::read(read.path);
-            if read.apply {
-                out.cleanup(&client).await;
+        Command::Inventory(command) => {
+            let inventory = inventory::Inventory::new(
+                &Lineage::describe(&client).await?,
+                command.group_by.as_deref().unwrap_or_default(),
+            );
+            match command.format {
+                InventoryFormat::Json => print!("{inventory}"),
+                InventoryFormat::Markdown => print!("{}", inventory.markdown()),
+                InventoryFormat::Html => print!("{}", inventory.page()),
+            }
+            Ok(Status::Clean)
+        }
+        Command::Explain(command) => {
+            if let Some(flag) = command.source.flags().first() {
+                return Err(Error::Unsupported {
+                    flag,
+                    command: "explain",
+                });
+            }
+            let mut trace = Trace::new(&command.id)?;
+            // Attributes only inform the verdicts, saved plans are explained without them.
+            if let Err(error) = trace.describe(&client).await {
+                log::warn!("Cannot describe {}: {}", command.id, error.report());
+            }
+            let out = match &command.source {
+                Source::Volume(command) => volumes(&client, command, &mut trace).await?,
+                Source::Snapshot(command) => snapshots(&client, command, &mut trace).await?,
+                Source::Image(command) => {
+                    let (images, inventory) = images(&client, command, &mut trace).await?;
+                    Out::new(Resources::Images(images), inventory)
+                }
+                Source::All(command) => all(&client, command, &mut trace).await?,
+                Source::Read(read) => Out::read(read.path.clone())?,
+            };
+            trace.plan(&out);
+
+            print!("{trace}");
+            if trace.is_planned() {
+                Ok(Status::Candidates)
             } else {
-                println!("{out}")
+                Ok(Status::Clean)
             }
         }
+        Command::Tui(command) => {
+            // The UI applies on demand, confirming first if asked to.
+            let flags = command.source.flags();
+            if let Some(flag) = flags.iter().find(|flag| **flag != "--interactive") {
+                return Err(Error::Unsupported {
+                    flag,
+                    command: "tui",
+                });
+            }
+            let prompt = Prompt {
+                interactive: flags.contains(&"--interactive"),
+                review: false,
+            };
+
+            // Read plans are checked as read, failing only when applied, and once curated.
+            let (mut out, output, guards, parallelism, journal, verified) = match command.source {
+                Source::Volume(command) => (
+                    volumes(&client, &command, &mut Trace::default()).await?,
+                    command.output,
+                    command.guards,
+                    command.execution.parallelism,
+                    Journal::default(),
+                    None,
+                ),
+                Source::Snapshot(command) => (
+                    snapshots(&client, &command, &mut Trace::default()).await?,
+                    command.output,
+                    command.guards,
+                    command.execution.parallelism,
+                    Journal::default(),
+                    None,
+                ),
+                Source::Image(command) => {
+                    let (images, inventory) =
+                        images(&client, &command, &mut Trace::default()).await?;
+                    (
+                        Out::new(Resources::Images(images), inventory),
+                        command.output,
+                        command.guards,
+                        command.execution.parallelism,
+                        Journal::default(),
+                        None,
+                    )
+                }
+                Source::All(command) => (
+                    all(&client, &command, &mut Trace::default()).await?,
+                    command.output,
+                    command.guards,
+                    command.execution.parallelism,
+                    Journal::default(),
+                    None,
+                ),
+                Source::Read(read) => {
+                    let out = Out::read(read.path.clone())?;
+                    let verified = Some(signature::verify(
+                        &out,
+                        &read.path,
+                        read.trusted_key.as_deref().unwrap_or_default(),
+                        read.allow_unsigned,
+                    ));
+                    (
+                        out,
+                        Some(read.path.clone()),
+                        read.guards,
+                        read.execution.parallelism,
+                        Journal::new(&read.path, read.resume)?,
+                        verified,
+                    )
+                }
+            };
+
+            match tui::curate(&mut out, output.is_some())? {
+                Outcome::Quit if out.is_empty() => Ok(Status::Clean),
+                Outcome::Quit => Ok(Status::Candidates),
+                Outcome::Save => {
+                    handle(&client, out, output, None, &prompt, preflight, print).await
+                }
+                Outcome::Apply => {
+                    let verified = verified.transpose()?;
+                    handle(
+                        &client,
+                        out,
+                        None,
+                        Some((&guards, parallelism, journal, verified.as_ref())),
+                        &prompt,
+                        preflight,
+                        print,
+                    )
+                    .await
+                }
+            }
+        }
+    }
+}
+
+/// Plans the deletion of orphaned volumes.
+async fn volumes(client: &Client, command: &options::Volume, trace: &mut Trace) -> Result<Out> {
+    let selection = Selection::parse(command.filter.as_deref(), command.condition.as_deref())?;
+    let server = selection.server(Kind::Volume);
+    let builder = VolumesBuilder::new(
+        client,
+        DescribeVolumes::names(command.names.clone()).filters(server.clone()),
+    )
+    .await?;
+    trace.stage(
+        Kind::Volume,
+        "server filters",
+        format!(
+            "available, {}, {} --filter terms",
+            explain::options(&[("--names", command.names.as_deref())]),
+            server.len()
+        ),
+        |id| builder.contains(id),
+    );
+
+    let builder = builder.filter(&selection);
+    trace.stage(
+        Kind::Volume,
+        "filter",
+        explain::options(&[
+            ("--filter", command.filter.as_deref()),
+            (
+                "--where",
+                command.condition.as_ref().map(std::slice::from_ref),
+            ),
+        ]),
+        |id| builder.contains(id),
+    );
+
+    let inventory = Inventory {
+        total: builder.candidates(),
+        ..Default::default()
+    };
+    let volumes = builder.build().await;
+    Ok(Out::new(Resources::Volumes(volumes), inventory))
+}
+
+/// Plans the deletion of orphaned snapshots.
+async fn snapshots(client: &Client, command: &options::Snapshot, trace: &mut Trace) -> Result<Out> {
+    let selection = Selection::parse(command.filter.as_deref(), command.condition.as_deref())?;
+    let server = selection.server(Kind::Snapshot);
+    let builder = SnapshotsBuilder::new(
+        client,
+        DescribeSnapshots::names(command.names.clone()).filters(server.clone()),
+    )
+    .await?;
+    trace.stage(
+        Kind::Snapshot,
+        "server filters",
+        format!(
+            "{}, {} --filter terms",
+            explain::options(&[("--names", command.names.as_deref())]),
+            server.len()
+        ),
+        |id| builder.contains(id),
+    );
+
+    let builder = builder.filter(&selection);
+    trace.stage(
+        Kind::Snapshot,
+        "filter",
+        explain::options(&[
+            ("--filter", command.filter.as_deref()),
+            (
+                "--where",
+                command.condition.as_ref().map(std::slice::from_ref),
+            ),
+        ]),
+        |id| builder.contains(id),
+    );
+
+    let total = builder.candidates();
+    let (builder, retained) = match &command.retention {
+        Some(Retention::Gfs(gfs)) => {
+            let (builder, retained) = builder.gfs(&Schedule::new(gfs)?);
+            let data = match trace.id().and_then(|id| retained.get(id)) {
+                Some(rule) => format!("retained as {rule} per volume"),
+                None => "retained by no rule per volume".to_owned(),
+            };
+            trace.stage(Kind::Snapshot, "gfs", data, |id| builder.contains(id));
+            (builder, retained)
+        }
+        None => (builder, Default::default()),
+    };
+
+    let snapshots = builder.build(command.cascade.into()).await;
+    let inventory = Inventory {
+        total,
+        retained,
+        ..Default::default()
+    };
+    Ok(Out::new(Resources::Snapshots(snapshots), inventory))
+}
+
+/// Plans the deletion of unused images.
+async fn images(
+    client: &Client,
+    command: &options::Image,
+    trace: &mut Trace,
+) -> Result<(Images, Inventory)> {
+    let grouping = match &command.group_tag {
+        Some(key) => Grouping::Tag(key.clone()),
+        None => match &command.group {
+            Some(pattern) => Grouping::Name(error::regex(pattern)?),
+            None => Grouping::All,
+        },
+    };
+
+    let selection = Selection::parse(command.filter.as_deref(), command.condition.as_deref())?;
+    let server = selection.server(Kind::Image);
+    let builder = ImagesBuilder::new(
+        client,
+        DescribeImages {
+            names: command.names.clone(),
+            tags: command.tags.clone(),
+            filters: server.clone(),
+            ..Default::default()
+        },
+    )
+    .await?;
+    trace.stage(
+        Kind::Image,
+        "server filters",
+        format!(
+            "owned by self, {}, {} --filter terms",
+            explain::options(&[
+                ("--names", command.names.as_deref()),
+                ("--tags", command.tags.as_deref()),
+            ]),
+            server.len()
+        ),
+        |id| builder.contains(id),
+    );
+
+    let builder = builder.unused().await?;
+    trace.stage(
+        Kind::Image,
+        "unused",
+        "no instance launched from it".to_owned(),
+        |id| builder.contains(id),
+    );
+
+    let builder = builder.filter(&selection);
+    trace.stage(
+        Kind::Image,
+        "filter",
+        explain::options(&[
+            ("--filter", command.filter.as_deref()),
+            (
+                "--where",
+                command.condition.as_ref().map(std::slice::from_ref),
+            ),
+        ]),
+        |id| builder.contains(id),
+    );
+
+    let builder = if command.version_pattern.is_some() || command.version_tag.is_some() {
+        builder.sort_by_version(&Versioning {
+            pattern: command
+                .version_pattern
+                .as_deref()
+                .map(error::regex)
+                .transpose()?,
+            tag: command.version_tag.clone(),
+            order: command.version_order,
+        })
+    } else {
+        builder
+    };
+
+    let builder = if let Some(names) = &command.exclude_names {
+        builder.exclude_names(names.clone())?
+    } else {
+        builder
+    };
+    trace.stage(
+        Kind::Image,
+        "exclude-names",
+        explain::options(&[("--exclude-names", command.exclude_names.as_deref())]),
+        |id| builder.contains(id),
+    );
+
+    let builder = if let Some(tags) = &command.exclude_tags {
+        builder.exclude_tags(tags.clone())?
+    } else {
+        builder
+    };
+    trace.stage(
+        Kind::Image,
+        "exclude-tags",
+        explain::options(&[("--exclude-tags", command.exclude_tags.as_deref())]),
+        |id| builder.contains(id),
+    );
+
+    let total = builder.candidates();
+    let group = match &grouping {
+        Grouping::All => "of all".to_owned(),
+        Grouping::Tag(key) => format!("per tag {key}"),
+        Grouping::Name(regex) => format!("per name {regex}"),
+    };
+    let (builder, retained, stage, data) = match &command.subcommand {
+        SubCommand::Keep(keep) => (
+            builder.keep(keep.keep, &grouping),
+            Default::default(),
+            "keep",
+            format!("{} newest kept {group}", keep.keep),
+        ),
+        SubCommand::Before(before) => {
+            let date = before.date().ok_or(Error::DateOutOfRange)?;
+            (
+                builder.before(date),
+                Default::default(),
+                "before",
+                format!("created before {date}"),
+            )
+        }
+        SubCommand::Gfs(gfs) => {
+            let (builder, retained) = builder.gfs(&Schedule::new(gfs)?, &grouping)?;
+            let data = match trace.id().and_then(|id| retained.get(id)) {
+                Some(rule) => format!("retained as {rule} {group}"),
+                None => format!("retained by no rule {group}"),
+            };
+            (builder, retained, "gfs", data)
+        }
+        SubCommand::Unlaunched(unlaunched) => {
+            let since = unlaunched.since.date().ok_or(Error::DateOutOfRange)?;
+            (
+                builder.unlaunched(since, unlaunched.never_launched).await?,
+                Default::default(),
+                "unlaunched",
+                format!(
+                    "last launched before {since}, never launched ones: {}",
+                    format!("{:?}", unlaunched.never_launched).to_lowercase()
+                ),
+            )
+        }
+    };
+    trace.stage(Kind::Image, stage, data, |id| builder.contains(id));
+
+    let images = builder.build(&grouping, command.cascade).await?;
+    let mut inventory = image::inventory(client, &images, &grouping).await?;
+    inventory.total = total;
+    inventory.retained = retained;
+    Ok((images, inventory))
+}
+
+/// Plans the deletion of unused images, then of the snapshots they leave orphaned, then of the
+/// volumes restored from those snapshots.
+///
+/// Each kind is evaluated against the deletions planned before it, never deleting twice.
+async fn all(client: &Client, command: &options::Image, trace: &mut Trace) -> Result<Out> {
+    let (images, mut inventory) = images(client, command, trace).await?;
+    let selection = Selection::parse(command.filter.as_deref(), command.condition.as_deref())?;
+    let filter = explain::options(&[
+        ("--filter", command.filter.as_deref()),
+        (
+            "--where",
+            command.condition.as_ref().map(std::slice::from_ref),
+        ),
+    ]);
+
+    let mut planned = out::ids(&images.entries());
+    let server = selection.server(Kind::Snapshot);
+    let builder = SnapshotsBuilder::new(
+        client,
+        DescribeSnapshots::default().owned().filters(server.clone()),
+    )
+    .await?;
+    trace.stage(
+        Kind::Snapshot,
+        "server filters",
+        format!("owned by self, {} --filter terms", server.len()),
+        |id| builder.contains(id),
+    );
+    let builder = builder.filter(&selection);
+    trace.stage(Kind::Snapshot, "filter", filter.clone(), |id| {
+        builder.contains(id)
+    });
+    inventory.total += builder.candidates();
+    let builder = builder.orphaned(&image::survivors(client, &images).await?, &planned);
+    trace.stage(
+        Kind::Snapshot,
+        "orphaned",
+        "created for an image that is gone or planned, backing none kept, not deleted with one"
+            .to_owned(),
+        |id| builder.contains(id),
+    );
+    let snapshots = builder.build(command.cascade).await;
+
+    planned.extend(out::ids(&snapshots.entries()));
+    // Other available volumes may be detached on purpose, only those of planned snapshots are left.
+    let restored = planned
+        .iter()
+        .filter(|id| id.starts_with("snap-"))
+        .cloned()
+        .collect();
+    let server = selection.server(Kind::Volume);
+    let builder = VolumesBuilder::new(
+        client,
+        DescribeVolumes::snapshot_ids(Some(restored)).filters(server.clone()),
+    )
+    .await?;
+    trace.stage(
+        Kind::Volume,
+        "server filters",
+        format!(
+            "available, restored from a planned snapshot, {} --filter terms",
+            server.len()
+        ),
+        |id| builder.contains(id),
+    );
+    let builder = builder.filter(&selection);
+    trace.stage(Kind::Volume, "filter", filter, |id| builder.contains(id));
+    let builder = builder.exclude(&planned);
+    trace.stage(
+        Kind::Volume,
+        "exclude",
+        "not deleted with a snapshot".to_owned(),
+        |id| builder.contains(id),
+    );
+    inventory.total += builder.candidates();
+    let volumes = builder.build().await;
+
+    Ok(Out::new(
+        Resources::All(All {
+            images,
+            snapshots,
+            volumes,
+        }),
+        inventory,
+    ))
+}
+
+/// How plans neither saved nor applied are printed.
+#[derive(Clone, Copy)]
+enum Print<'a> {
+    Json,
+    Table(&'a [String]),
+    Report(ReportFormat),
+}
+
+/// Reviews, then saves and applies within safety limits, or prints the plan, as a table or report if
+/// asked.
+async fn handle(
+    client: &Client,
+    mut out: Out,
+    output: Option<PathBuf>,
+    apply: Option<(&Guards, usize, Journal, Option<&Verified>)>,
+    prompt: &Prompt,
+    preflight: Option<&str>,
+    print: Print<'_>,
+) -> Result<Status> {
+    if prompt.review {
+        if output.is_none() && apply.is_none() {
+            return Err(Error::UnsavedReview);
+        }
+        review::review(&mut out)?;
+    }
+
+    let status = if out.is_empty() {
+        Status::Clean
+    } else {
+        Status::Candidates
+    };
+
+    let saved = output.is_some();
+    if let Some(path) = output {
+        out.write(path)?;
+    }
+
+    if status == Status::Clean {
+        log::info!("Nothing to clean");
+    } else if let Some((guards, parallelism, mut journal, verified)) = apply {
+        if let Some(verified) = verified {
+            verified.check(&out)?;
+        }
+        guard::check(&out, guards)?;
+        journal.bind(out.content_digest()?)?;
+
+        if prompt.interactive && !review::confirm(&out)? {
+            log::warn!("Aborted by operator");
+            return Ok(status);
+        }
+
+        if let Some(region) = preflight {
+            let checks = preflight::check(client, region, out.actions(), &out.samples()).await;
+            let denied = checks
+                .iter()
+                .filter(|check| check.is_denied())
+                .map(|check| check.action)
+                .collect::<Vec<_>>();
+            if !denied.is_empty() {
+                return Err(Error::MissingPermissions {
+                    region: region.to_owned(),
+                    actions: denied,
+                });
+            }
+        }
+
+        let (mut failed, mut skipped) = (0, 0);
+        for record in out.cleanup(client, parallelism, &mut journal).await {
+            match &record.outcome {
+                execution::Outcome::Deleted => {}
+                execution::Outcome::Failed(error) => {
+                    error::report(error);
+                    failed += 1;
+                }
+                execution::Outcome::Skipped(parent) => {
+                    log::warn!(
+                        "Skipped {} {}, {parent} was not deleted",
+                        record.kind,
+                        record.id
+                    );
+                    skipped += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            log::error!("{failed} deletions failed, {skipped} dependents skipped");
+            error::record(Status::PartialFailure);
+        }
+    } else if !saved {
+        match print {
+            Print::Json => println!("{out}"),
+            Print::Table(columns) => println!("{}", out.table(columns)),
+            Print::Report(ReportFormat::Markdown) => print!("{}", report::markdown(&out)),
+            Print::Report(ReportFormat::Html) => print!("{}", report::page(&out)),
+        }
     }
 
-    Ok(())
+    Ok(status)
 }
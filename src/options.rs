@@ -1,5 +1,5 @@
 use chrono::{DateTime, Duration, Utc};
-use clap::{Args, Parser, Subcommand};
+use clap::{ArgEnum, Args, Parser, Subcommand};
 use std::path::PathBuf;
 
 /// Search for Image's or orphan Snapshots/Volume to delete.
@@ -12,6 +12,18 @@ pub struct Options {
     #[clap(short, long, default_value = "default")]
     pub profile: String,
 
+    /// Skip the permission dry runs performed before applying
+    #[clap(long)]
+    pub no_preflight: bool,
+
+    /// Print the plan as a table of these columns instead of JSON, e.g. id,name,size,tag:Team
+    #[clap(short, long, use_value_delimiter = true)]
+    pub columns: Option<Vec<String>>,
+
+    /// Print the plan as a report with cost estimates instead of JSON
+    #[clap(long, arg_enum, conflicts_with = "columns")]
+    pub report: Option<ReportFormat>,
+
     /// If no command, handles orphan snapshots.
     #[clap(subcommand)]
     pub command: Command,
@@ -28,8 +40,91 @@ pub enum Command {
     /// Search for unused images to delete.
     Image(Image),
 
+    /// Search for unused images, then the snapshots and volumes left orphaned, to delete.
+    ///
+    /// Name and tag options select images, --filter and --where apply to every kind.
+    All(Image),
+
     /// Read previously generated resource list to delete.
     Read(Read),
+
+    /// Sign a saved plan as approved, in PATH.sig next to it.
+    Sign(Sign),
+
+    /// Compare two saved plans.
+    Diff(Diff),
+
+    /// Subset, edit, merge or split saved plans.
+    Plan(Plan),
+
+    /// Check IAM permissions using EC2 dry runs.
+    Preflight(Preflight),
+
+    /// Print the minimal IAM policy this tool needs.
+    Policy(Policy),
+
+    /// Export how images, snapshots, volumes and the instances using them relate, as a graph.
+    Lineage(Lineage),
+
+    /// Browse and curate a plan in a terminal UI.
+    Tui(Tui),
+
+    /// Show why a resource is planned for deletion or not, stage by stage.
+    Explain(Explain),
+
+    /// List every self-owned image, snapshot and volume, used or not, with its cost. Deletes nothing.
+    Inventory(Inventory),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum Kind {
+    Image,
+    Snapshot,
+    Volume,
+}
+
+/// Safety limits checked before applying.
+#[derive(Debug, Args)]
+pub struct Guards {
+    /// Maximum number of resources to delete, dependent ones included
+    #[clap(long)]
+    pub max_count: Option<usize>,
+
+    /// Maximum storage to free, in GiB
+    #[clap(long)]
+    pub max_size: Option<i64>,
+
+    /// Maximum percentage of the candidates matching the selection to delete
+    #[clap(long)]
+    pub max_percent: Option<f64>,
+
+    /// Minimum number of images left per group
+    #[clap(long)]
+    pub min_survivors: Option<usize>,
+
+    /// Apply even when exceeding safety limits
+    #[clap(long)]
+    pub force: bool,
+}
+
+/// How deletions are carried out.
+#[derive(Debug, Args)]
+pub struct Execution {
+    /// Maximum number of deletions in flight
+    #[clap(long, default_value = "8")]
+    pub parallelism: usize,
+}
+
+/// Operator checks performed on the plan.
+#[derive(Debug, Default, Args)]
+pub struct Prompt {
+    /// Show a summary and ask for confirmation before applying
+    #[clap(short, long)]
+    pub interactive: bool,
+
+    /// Accept, skip or protect each resource, then save the decisions
+    #[clap(long)]
+    pub review: bool,
 }
 
 #[derive(Debug, Args)]
@@ -38,10 +133,27 @@ pub struct Volume {
     #[clap(long)]
     pub apply: bool,
 
+    #[clap(flatten)]
+    pub guards: Guards,
+
+    #[clap(flatten)]
+    pub execution: Execution,
+
+    #[clap(flatten)]
+    pub prompt: Prompt,
+
     /// Filter by Tag:Name.
     #[clap(short, long)]
     pub names: Option<Vec<String>>,
 
+    /// Filter such as tag:Team=data*, name~^app- or !tag:Keep, repeat to combine
+    #[clap(short, long)]
+    pub filter: Option<Vec<String>>,
+
+    /// Expression such as "encrypted = false AND (age > 30d OR NOT tag:Owner)"
+    #[clap(short = 'w', long = "where")]
+    pub condition: Option<String>,
+
     /// Save result for later deletion
     #[clap(short, long)]
     pub output: Option<PathBuf>,
@@ -53,13 +165,43 @@ pub struct Snapshot {
     #[clap(long)]
     pub apply: bool,
 
+    #[clap(flatten)]
+    pub guards: Guards,
+
+    #[clap(flatten)]
+    pub execution: Execution,
+
+    #[clap(flatten)]
+    pub prompt: Prompt,
+
     /// Filter by Tag:Name.
     #[clap(short, long)]
     pub names: Option<Vec<String>>,
 
+    /// Filter such as tag:Team=data*, name~^app- or !tag:Keep, repeat to combine
+    #[clap(short, long)]
+    pub filter: Option<Vec<String>>,
+
+    /// Expression such as "encrypted = false AND (age > 30d OR NOT tag:Owner)"
+    #[clap(short = 'w', long = "where")]
+    pub condition: Option<String>,
+
+    /// Dependents deleted with each snapshot, volumes meaning the available ones restored from it
+    #[clap(long, arg_enum, default_value = "none")]
+    pub cascade: SnapshotCascade,
+
     /// Save result for later deletion
     #[clap(short, long)]
     pub output: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    pub retention: Option<Retention>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Retention {
+    /// Keep snapshots of each volume by grandfather-father-son schedule.
+    Gfs(Gfs),
 }
 
 #[derive(Debug, Args)]
@@ -68,6 +210,15 @@ pub struct Image {
     #[clap(long)]
     pub apply: bool,
 
+    #[clap(flatten)]
+    pub guards: Guards,
+
+    #[clap(flatten)]
+    pub execution: Execution,
+
+    #[clap(flatten)]
+    pub prompt: Prompt,
+
     /// Filter by Tags
     #[clap(short, long)]
     pub tags: Option<Vec<String>>,
@@ -84,6 +235,38 @@ pub struct Image {
     #[clap(short = 'N', long)]
     pub exclude_names: Option<Vec<String>>,
 
+    /// Regex whose first capture group names the image group, whole name otherwise, e.g. ^(.*)-\d{4}-\d{2}-\d{2}
+    #[clap(short, long)]
+    pub group: Option<String>,
+
+    /// Group images by the value of this tag instead of by name
+    #[clap(short = 'G', long, conflicts_with = "group")]
+    pub group_tag: Option<String>,
+
+    /// Regex whose first capture group is the version images are sorted by, newest first
+    #[clap(long)]
+    pub version_pattern: Option<String>,
+
+    /// Read the version from this tag instead of from the name
+    #[clap(long)]
+    pub version_tag: Option<String>,
+
+    /// How versions compare
+    #[clap(long, arg_enum, default_value = "semver")]
+    pub version_order: VersionOrder,
+
+    /// Dependents deleted with each image: none, backing snapshots, or those and restored volumes
+    #[clap(long, arg_enum, default_value = "snapshots")]
+    pub cascade: Cascade,
+
+    /// Filter such as tag:Team=data*, name~^app- or !tag:Keep, repeat to combine
+    #[clap(short, long)]
+    pub filter: Option<Vec<String>>,
+
+    /// Expression such as "encrypted = false AND (age > 30d OR NOT tag:Owner)"
+    #[clap(short = 'w', long = "where")]
+    pub condition: Option<String>,
+
     /// Save result for later deletion
     #[clap(short, long)]
     pub output: Option<PathBuf>,
@@ -92,18 +275,63 @@ pub struct Image {
     pub subcommand: SubCommand,
 }
 
+/// Dependent resources planned, then deleted, along with a resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+pub enum Cascade {
+    /// The resource alone
+    None,
+    /// Snapshots backing an image
+    Snapshots,
+    /// Snapshots, and available volumes restored from them
+    Volumes,
+}
+
+/// Dependent resources of a snapshot, which no snapshot backs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum SnapshotCascade {
+    /// The snapshot alone
+    None,
+    /// Available volumes restored from it
+    Volumes,
+}
+
+impl From<SnapshotCascade> for Cascade {
+    fn from(cascade: SnapshotCascade) -> Self {
+        match cascade {
+            SnapshotCascade::None => Self::None,
+            SnapshotCascade::Volumes => Self::Volumes,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum VersionOrder {
+    /// Semantic versions, an optional leading v aside
+    Semver,
+    /// Sequences of numbers, whatever separates them
+    Numeric,
+    /// Plain strings
+    Lexical,
+}
+
 #[derive(Debug, Subcommand)]
 pub enum SubCommand {
-    /// How many images to keep.
+    /// How many images to keep in each group.
     Keep(Keep),
 
     /// Image's expiration date.
     Before(Before),
+
+    /// Keep images of each group by grandfather-father-son schedule.
+    Gfs(Gfs),
+
+    /// Images not launched since the given time.
+    Unlaunched(Unlaunched),
 }
 
 #[derive(Debug, Parser)]
 pub struct Keep {
-    /// How many matching images to keep.
+    /// How many of the newest images to keep in each group.
     #[clap(default_value_t = 2)]
     pub keep: usize,
 }
@@ -118,24 +346,332 @@ pub struct Before {
     pub weeks: i64,
 }
 
-impl Into<DateTime<Utc>> for Before {
-    fn into(self) -> DateTime<Utc> {
-        Utc::now()
-            .checked_sub_signed(
-                Duration::weeks(self.weeks)
-                    + Duration::days(self.days)
-                    + Duration::hours(self.hours),
-            )
-            .expect("Invalid date")
+impl Before {
+    /// Expiration date, `None` when out of range.
+    pub fn date(&self) -> Option<DateTime<Utc>> {
+        let duration = Duration::try_weeks(self.weeks)?
+            .checked_add(&Duration::try_days(self.days)?)?
+            .checked_add(&Duration::try_hours(self.hours)?)?;
+
+        Utc::now().checked_sub_signed(duration)
     }
 }
 
+#[derive(Debug, Parser)]
+pub struct Unlaunched {
+    #[clap(flatten)]
+    pub since: Before,
+
+    /// What images never launched are judged by
+    #[clap(long, arg_enum, default_value = "creation")]
+    pub never_launched: NeverLaunched,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum NeverLaunched {
+    /// Their creation date
+    Creation,
+    /// Always kept
+    Keep,
+    /// Always deleted
+    Delete,
+}
+
+/// Keeps the newest item of each of the last days, weeks, months and years.
+#[derive(Debug, Args)]
+pub struct Gfs {
+    /// Days to keep the newest item of
+    #[clap(long, default_value_t = 0)]
+    pub daily: usize,
+
+    /// ISO weeks to keep the newest item of
+    #[clap(long, default_value_t = 0)]
+    pub weekly: usize,
+
+    /// Months to keep the newest item of
+    #[clap(long, default_value_t = 0)]
+    pub monthly: usize,
+
+    /// Years to keep the newest item of
+    #[clap(long, default_value_t = 0)]
+    pub yearly: usize,
+
+    /// IANA timezone periods are computed in
+    #[clap(long, default_value = "UTC")]
+    pub timezone: String,
+}
+
 #[derive(Debug, Args)]
 pub struct Read {
     /// Effectively deletes images
     #[clap(long)]
     pub apply: bool,
 
-    /// Path to read data from
+    #[clap(flatten)]
+    pub guards: Guards,
+
+    #[clap(flatten)]
+    pub execution: Execution,
+
+    #[clap(flatten)]
+    pub prompt: Prompt,
+
+    /// Continue an interrupted apply, skipping what its journal records as deleted, the plan as it
+    /// was applied
+    #[clap(long, requires = "apply", conflicts_with = "review")]
+    pub resume: bool,
+
+    /// Public key, 64 hex digits, whose signature is required to apply, repeat to allow several
+    #[clap(long)]
+    pub trusted_key: Option<Vec<String>>,
+
+    /// Apply a plan lacking a digest, as saved by older versions, without checking its integrity
+    #[clap(long, conflicts_with = "trusted-key")]
+    pub allow_unsigned: bool,
+
+    /// Path to read data from, and to save review decisions to
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct Sign {
+    /// Secret key, 64 hex digits, generated when missing
+    #[clap(short, long)]
+    pub key: PathBuf,
+
+    /// Plan to sign, as saved with --output
     pub path: PathBuf,
 }
+
+#[derive(Debug, Args)]
+pub struct Plan {
+    #[clap(subcommand)]
+    pub command: PlanCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PlanCommand {
+    /// Keep the top level resources matching every criterion, along with their dependents.
+    Filter(PlanFilter),
+
+    /// Drop resources by ID, dependent ones included.
+    Remove(PlanRemove),
+
+    /// Combine plans, mixed kinds into one covering them all.
+    Merge(PlanMerge),
+
+    /// Split a plan into batches, saved next to it as NAME.1.json, NAME.2.json...
+    Split(PlanSplit),
+}
+
+#[derive(Debug, Args)]
+pub struct PlanFilter {
+    /// Resource IDs to keep, comma separated
+    #[clap(long, use_value_delimiter = true, multiple_values = false)]
+    pub ids: Option<Vec<String>>,
+
+    /// Regex names must match
+    #[clap(short, long)]
+    pub regex: Option<String>,
+
+    /// Tag to match as KEY=VALUE, repeat to combine
+    #[clap(short, long, multiple_values = false)]
+    pub tags: Option<Vec<String>>,
+
+    // Resources older than the duration, when any is given.
+    #[clap(flatten)]
+    pub older: Before,
+
+    /// Path to save the filtered plan to
+    #[clap(short, long)]
+    pub output: PathBuf,
+
+    /// Plan to filter
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct PlanRemove {
+    /// Resource IDs to drop, comma separated
+    #[clap(
+        long,
+        required = true,
+        use_value_delimiter = true,
+        multiple_values = false
+    )]
+    pub ids: Vec<String>,
+
+    /// Path to save the edited plan to
+    #[clap(short, long)]
+    pub output: PathBuf,
+
+    /// Plan to edit
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct PlanMerge {
+    /// Path to save the merged plan to
+    #[clap(short, long)]
+    pub output: PathBuf,
+
+    /// Plans to merge
+    #[clap(required = true, min_values = 2)]
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct PlanSplit {
+    /// Top level resources per batch
+    #[clap(short, long)]
+    pub batch: usize,
+
+    /// Plan to split
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct Diff {
+    /// Format of the differences
+    #[clap(long, arg_enum, default_value = "human")]
+    pub format: DiffFormat,
+
+    /// Plan before
+    pub old: PathBuf,
+
+    /// Plan after
+    pub new: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum DiffFormat {
+    /// One line per resource, + added, - removed, ~ changed
+    Human,
+    Json,
+    /// Tables to post in a review
+    Markdown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum ReportFormat {
+    /// A collapsible section per resource listing its dependents
+    Markdown,
+    /// Standalone page with totals and sortable tables
+    Html,
+}
+
+#[derive(Debug, Args)]
+pub struct Preflight {
+    /// Regions to check, defaults to --region
+    #[clap(short = 'R', long)]
+    pub regions: Option<Vec<String>>,
+}
+
+#[derive(Debug, Args)]
+pub struct Lineage {
+    #[clap(short, long, arg_enum, default_value = "dot")]
+    pub format: GraphFormat,
+
+    /// Saved plan whose deletions to highlight
+    #[clap(long)]
+    pub plan: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum GraphFormat {
+    /// Graphviz, e.g. piped to `dot -Tsvg`
+    Dot,
+    /// Flowchart, rendered in Markdown by GitHub and GitLab
+    Mermaid,
+}
+
+#[derive(Debug, Args)]
+pub struct Policy {
+    /// Allow deletions, not only planning
+    #[clap(long)]
+    pub apply: bool,
+
+    /// Resource kinds to cover, defaults to all
+    #[clap(short, long, arg_enum)]
+    pub kinds: Option<Vec<Kind>>,
+
+    /// Only allow deleting images, or snapshots if not covered, tagged KEY=VALUE
+    #[clap(short, long)]
+    pub tags: Option<Vec<String>>,
+}
+
+#[derive(Debug, Args)]
+pub struct Tui {
+    #[clap(subcommand)]
+    pub source: Source,
+}
+
+#[derive(Debug, Args)]
+pub struct Inventory {
+    /// Tag keys to group resources by, e.g. Team,Project
+    #[clap(short, long, use_value_delimiter = true)]
+    pub group_by: Option<Vec<String>>,
+
+    #[clap(short, long, arg_enum, default_value = "json")]
+    pub format: InventoryFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum InventoryFormat {
+    Json,
+    /// A table per group, to publish as is
+    Markdown,
+    /// Standalone page with sortable tables, unused resources highlighted
+    Html,
+}
+
+#[derive(Debug, Args)]
+pub struct Explain {
+    /// Image, snapshot or volume ID
+    pub id: String,
+
+    /// Pipeline to walk, with the options the plan was made with
+    #[clap(subcommand)]
+    pub source: Source,
+}
+
+/// Where the curated or explained plan comes from, curated plans being saved to its path or --output.
+///
+/// Apply and review flags are refused: the UI applies on demand, explain never does.
+#[derive(Debug, Subcommand)]
+pub enum Source {
+    /// Search for orphaned volumes.
+    Volume(Volume),
+
+    /// Search for orphaned snaphots.
+    Snapshot(Snapshot),
+
+    /// Search for unused images.
+    Image(Image),
+
+    /// Search for unused images, then the snapshots and volumes left orphaned.
+    All(Image),
+
+    /// Read a previously generated plan.
+    Read(Read),
+}
+
+impl Source {
+    /// Apply and prompt flags given.
+    pub fn flags(&self) -> Vec<&'static str> {
+        let (apply, prompt) = match self {
+            Self::Volume(command) => (command.apply, &command.prompt),
+            Self::Snapshot(command) => (command.apply, &command.prompt),
+            Self::Image(command) | Self::All(command) => (command.apply, &command.prompt),
+            Self::Read(read) => (read.apply, &read.prompt),
+        };
+        [
+            (apply, "--apply"),
+            (prompt.review, "--review"),
+            (prompt.interactive, "--interactive"),
+        ]
+        .into_iter()
+        .filter_map(|(given, flag)| given.then_some(flag))
+        .collect()
+    }
+}
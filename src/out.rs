@@ -1,41 +1,539 @@
-use crate::aws::{image::Images, snapshot::Snapshots, volume::Volumes};
+use crate::aws::{
+    execution::{self, Record},
+    image::{self, Images},
+    permission::Action,
+    preflight::Samples,
+    snapshot::{self, Snapshots},
+    volume::{self, Volumes},
+};
 use aws_sdk_ec2::Client;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::PathBuf,
+};
+
+use crate::{
+    error::{Error, Result},
+    guard::Inventory,
+    journal::Journal,
+    options::Kind,
+};
+
+/// Uniform view of a plan entry and its dependents.
+pub struct Entry {
+    pub kind: Kind,
+    pub id: String,
+    pub name: String,
+    pub date: Option<String>,
+    /// Storage freed by deleting the entry and its dependents, in GiB.
+    pub size: i64,
+    /// Storage of the entry alone, in GiB, 0 for images.
+    pub own_size: i64,
+    pub protected: bool,
+    pub tags: BTreeMap<String, String>,
+    /// Kind specific metadata, by column name.
+    pub details: BTreeMap<&'static str, String>,
+    /// Resources deleted along with the entry.
+    pub children: Vec<Entry>,
+}
+
+/// Columns of the human output, besides `tag:KEY`.
+pub const COLUMNS: &[&str] = &[
+    "kind",
+    "id",
+    "name",
+    "date",
+    "size",
+    "protected",
+    "zone",
+    "type",
+    "iops",
+    "throughput",
+    "encrypted",
+    "kms_key",
+    "description",
+    "tier",
+    "architecture",
+    "platform",
+    "owner",
+    "last_launched",
+    "group",
+    "reason",
+];
+
+/// Keeps the known details.
+pub fn details<const N: usize>(
+    details: [(&'static str, Option<String>); N],
+) -> BTreeMap<&'static str, String> {
+    details
+        .into_iter()
+        .filter_map(|(column, value)| Some((column, value?)))
+        .collect()
+}
+
+/// Checks every column is known.
+pub fn columns(columns: &[String]) -> Result<()> {
+    match columns
+        .iter()
+        .find(|column| !COLUMNS.contains(&column.as_str()) && !column.starts_with("tag:"))
+    {
+        Some(column) => Err(Error::InvalidColumn(column.clone())),
+        None => Ok(()),
+    }
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Image => "image",
+            Self::Snapshot => "snapshot",
+            Self::Volume => "volume",
+        })
+    }
+}
+
+impl Entry {
+    /// Value of the column, `-` when unknown.
+    pub fn column(&self, column: &str) -> String {
+        match column {
+            "kind" => self.kind.to_string(),
+            "id" => self.id.clone(),
+            "name" => self.name.clone(),
+            "date" => self.date.clone().unwrap_or_else(|| "-".to_owned()),
+            "size" => self.size.to_string(),
+            "protected" => self.protected.to_string(),
+            column => column
+                .strip_prefix("tag:")
+                .map_or_else(|| self.details.get(column), |key| self.tags.get(key))
+                .cloned()
+                .unwrap_or_else(|| "-".to_owned()),
+        }
+    }
+}
+
+/// Every kind, in the order they are evaluated and deleted.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct All {
+    pub images: Images,
+    /// Orphaned once the images are deregistered, their backing snapshots excluded.
+    pub snapshots: Snapshots,
+    /// Available ones, those restored from the snapshots above excluded.
+    pub volumes: Volumes,
+}
 
 #[derive(Serialize, Deserialize)]
-pub enum Out {
+pub enum Resources {
     Images(Images),
     Snapshots(Snapshots),
     Volumes(Volumes),
+    All(All),
+}
+
+/// IDs of the entries and their dependents.
+pub fn ids(entries: &[Entry]) -> HashSet<String> {
+    entries
+        .iter()
+        .flat_map(|entry| {
+            let mut ids = ids(&entry.children);
+            ids.insert(entry.id.clone());
+            ids
+        })
+        .collect()
+}
+
+/// IDs of the entries applying deletes, protected ones and their dependents excluded.
+pub fn pending(entries: &[Entry]) -> HashSet<String> {
+    entries
+        .iter()
+        .filter(|entry| !entry.protected)
+        .flat_map(|entry| {
+            let mut ids = pending(&entry.children);
+            ids.insert(entry.id.clone());
+            ids
+        })
+        .collect()
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Out {
+    #[serde(flatten)]
+    pub resources: Resources,
+
+    /// Inventory the plan was built against, missing from older plans.
+    #[serde(default)]
+    pub inventory: Inventory,
+
+    /// SHA-256 of the rest of the plan as written, hex encoded, missing from older plans.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
 }
 
 impl Out {
-    pub fn read(path: PathBuf) -> Self {
-        let file = std::fs::File::open(path).expect("Disc read failure");
-        serde_json::from_reader(file).expect("Deserialization failure")
+    pub fn new(resources: Resources, inventory: Inventory) -> Self {
+        Self {
+            resources,
+            inventory,
+            digest: None,
+        }
+    }
+
+    pub fn read(path: PathBuf) -> Result<Self> {
+        let file = std::fs::File::open(&path).map_err(|source| Error::Io { path, source })?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    /// SHA-256 of the plan, its recorded digest excluded, over JSON with sorted keys.
+    pub fn content_digest(&self) -> Result<String> {
+        let mut value = serde_json::to_value(self)?;
+        if let Some(object) = value.as_object_mut() {
+            object.remove("digest");
+        }
+        Ok(hex::encode(Sha256::digest(serde_json::to_vec(&value)?)))
+    }
+
+    /// Saves the plan along with its digest.
+    pub fn write(&mut self, path: PathBuf) -> Result<()> {
+        self.digest = Some(self.content_digest()?);
+        let serialized = serde_json::to_string(&self)?;
+        std::fs::write(&path, serialized).map_err(|source| Error::Io { path, source })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match &self.resources {
+            Resources::Images(images) => images.is_empty(),
+            Resources::Snapshots(snapshots) => snapshots.is_empty(),
+            Resources::Volumes(volumes) => volumes.is_empty(),
+            Resources::All(all) => {
+                all.images.is_empty() && all.snapshots.is_empty() && all.volumes.is_empty()
+            }
+        }
+    }
+
+    /// Number of top level resources, dependent ones excluded.
+    pub fn len(&self) -> usize {
+        match &self.resources {
+            Resources::Images(images) => images.len(),
+            Resources::Snapshots(snapshots) => snapshots.len(),
+            Resources::Volumes(volumes) => volumes.len(),
+            Resources::All(all) => all.images.len() + all.snapshots.len() + all.volumes.len(),
+        }
+    }
+
+    /// Number of resources to delete, dependent ones included.
+    pub fn count(&self) -> usize {
+        match &self.resources {
+            Resources::Images(images) => images.count(),
+            Resources::Snapshots(snapshots) => snapshots.count(),
+            Resources::Volumes(volumes) => volumes.count(),
+            Resources::All(all) => all.images.count() + all.snapshots.count() + all.volumes.count(),
+        }
     }
 
-    pub fn write(&self, path: PathBuf) {
-        let serialized = serde_json::to_string(&self).expect("Serialization failure");
-        std::fs::write(path, serialized).expect("Disc write failure");
+    /// Storage to free, in GiB.
+    pub fn size(&self) -> i64 {
+        match &self.resources {
+            Resources::Images(images) => images.size(),
+            Resources::Snapshots(snapshots) => snapshots.size(),
+            Resources::Volumes(volumes) => volumes.size(),
+            Resources::All(all) => all.images.size() + all.snapshots.size() + all.volumes.size(),
+        }
     }
 
-    pub async fn cleanup(&self, client: &Client) {
+    pub fn entries(&self) -> Vec<Entry> {
+        match &self.resources {
+            Resources::Images(images) => images.entries(),
+            Resources::Snapshots(snapshots) => snapshots.entries(),
+            Resources::Volumes(volumes) => volumes.entries(),
+            Resources::All(all) => {
+                let mut entries = all.images.entries();
+                entries.extend(all.snapshots.entries());
+                entries.extend(all.volumes.entries());
+                entries
+            }
+        }
+    }
+
+    /// Drops skipped entries, then, given the complete set of protected top level entries, flags
+    /// those, which are then never deleted, and unflags the others.
+    pub fn decide(&mut self, skipped: &HashSet<String>, protected: Option<&HashSet<String>>) {
+        match &mut self.resources {
+            Resources::Images(images) => images.decide(skipped, protected),
+            Resources::Snapshots(snapshots) => snapshots.decide(skipped, protected),
+            Resources::Volumes(volumes) => volumes.decide(skipped, protected),
+            Resources::All(all) => {
+                all.images.decide(skipped, protected);
+                all.snapshots.decide(skipped, protected);
+                all.volumes.decide(skipped, protected);
+            }
+        }
+    }
+
+    /// Actions needed to apply the plan.
+    pub fn actions(&self) -> &'static [Action] {
+        match &self.resources {
+            Resources::Images(_) => image::APPLY,
+            Resources::Snapshots(_) => snapshot::APPLY,
+            Resources::Volumes(_) => volume::APPLY,
+            Resources::All(_) => image::APPLY,
+        }
+    }
+
+    pub fn samples(&self) -> Samples {
+        let mut samples = Samples::default();
+        match &self.resources {
+            Resources::Images(images) => images.samples(&mut samples),
+            Resources::Snapshots(snapshots) => snapshots.samples(&mut samples),
+            Resources::Volumes(volumes) => volumes.samples(&mut samples),
+            Resources::All(all) => {
+                all.images.samples(&mut samples);
+                all.snapshots.samples(&mut samples);
+                all.volumes.samples(&mut samples);
+            }
+        }
+        samples
+    }
+
+    /// Deletes images first, then snapshots, then volumes, each after the resource it depends on.
+    ///
+    /// Snapshots created for an image that failed to deregister are skipped, the image still using
+    /// them.
+    pub async fn cleanup(
+        self,
+        client: &Client,
+        parallelism: usize,
+        journal: &mut Journal,
+    ) -> Vec<Record> {
+        let Resources::All(all) = self.resources else {
+            return execution::execute(client, vec![self.entries()], parallelism, journal).await;
+        };
+
+        let mut records =
+            execution::execute(client, vec![all.images.entries()], parallelism, journal).await;
+        let failed = records
+            .iter()
+            .filter(|record| !matches!(record.outcome, execution::Outcome::Deleted))
+            .map(|record| record.id.clone())
+            .collect::<HashSet<_>>();
+
+        let mut snapshots = vec![];
+        for entry in all.snapshots.entries() {
+            let image = entry
+                .details
+                .get("description")
+                .and_then(|description| snapshot::image_id(description))
+                .filter(|image| failed.contains(*image))
+                .map(str::to_owned);
+            match image {
+                Some(image) => records.extend(execution::hold(vec![entry], &image, journal)),
+                None => snapshots.push(entry),
+            }
+        }
+
+        records.extend(
+            execution::execute(
+                client,
+                vec![snapshots, all.volumes.entries()],
+                parallelism,
+                journal,
+            )
+            .await,
+        );
+        records
+    }
+}
+
+impl Resources {
+    fn into_all(self) -> All {
         match self {
-            Self::Images(images) => images.cleanup(&client).await,
-            Self::Snapshots(snapshots) => snapshots.cleanup(&client).await,
-            Self::Volumes(volumes) => volumes.cleanup(&client).await,
+            Self::Images(images) => All {
+                images,
+                snapshots: Snapshots::default(),
+                volumes: Volumes::default(),
+            },
+            Self::Snapshots(snapshots) => All {
+                images: Images::default(),
+                snapshots,
+                volumes: Volumes::default(),
+            },
+            Self::Volumes(volumes) => All {
+                images: Images::default(),
+                snapshots: Snapshots::default(),
+                volumes,
+            },
+            Self::All(all) => all,
         }
     }
 }
 
+impl Out {
+    /// Combines two plans, mixed kinds into one covering them all, resources planned twice once.
+    ///
+    /// Limits stay as strict as the strictest plan: the larger inventory of a kind and the fewest
+    /// survivors of a group are kept.
+    pub fn merge(self, other: Out) -> Out {
+        let same =
+            std::mem::discriminant(&self.resources) == std::mem::discriminant(&other.resources);
+        let resources = match (self.resources, other.resources) {
+            (Resources::Images(mut images), Resources::Images(other)) => {
+                images.extend(other);
+                Resources::Images(images)
+            }
+            (Resources::Snapshots(mut snapshots), Resources::Snapshots(other)) => {
+                snapshots.extend(other);
+                Resources::Snapshots(snapshots)
+            }
+            (Resources::Volumes(mut volumes), Resources::Volumes(other)) => {
+                volumes.extend(other);
+                Resources::Volumes(volumes)
+            }
+            (resources, other) => {
+                let (mut all, other) = (resources.into_all(), other.into_all());
+                all.images.extend(other.images);
+                all.snapshots.extend(other.snapshots);
+                all.volumes.extend(other.volumes);
+                Resources::All(all)
+            }
+        };
+
+        let mut inventory = self.inventory;
+        inventory.total = if same {
+            inventory.total.max(other.inventory.total)
+        } else {
+            inventory.total + other.inventory.total
+        };
+        for (group, survivors) in other.inventory.survivors {
+            let count = inventory.survivors.entry(group).or_insert(survivors);
+            *count = (*count).min(survivors);
+        }
+        inventory.retained.extend(other.inventory.retained);
+
+        Out::new(resources, inventory)
+    }
+}
+
+impl Out {
+    /// Aligned table of the entries and their dependents, indented below them.
+    pub fn table(&self, columns: &[String]) -> String {
+        fn rows(entries: &[Entry], columns: &[String], depth: usize, table: &mut Vec<Vec<String>>) {
+            for entry in entries {
+                let mut row = columns
+                    .iter()
+                    .map(|column| entry.column(column))
+                    .collect::<Vec<_>>();
+                if let Some(first) = row.first_mut() {
+                    first.insert_str(0, &"  ".repeat(depth));
+                }
+                table.push(row);
+                rows(&entry.children, columns, depth + 1, table);
+            }
+        }
+
+        let mut table = vec![columns
+            .iter()
+            .map(|column| match column.strip_prefix("tag:") {
+                Some(key) => format!("TAG:{key}"),
+                None => column.to_uppercase(),
+            })
+            .collect::<Vec<_>>()];
+        rows(&self.entries(), columns, 0, &mut table);
+
+        let widths = (0..columns.len())
+            .map(|index| {
+                table
+                    .iter()
+                    .map(|row| row[index].chars().count())
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+
+        table
+            .iter()
+            .map(|row| {
+                std::iter::zip(row, &widths)
+                    .map(|(value, width)| format!("{value:<width$}"))
+                    .collect::<Vec<_>>()
+                    .join("  ")
+                    .trim_end()
+                    .to_owned()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
 impl std::fmt::Display for Out {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(
             f,
             "{}",
-            serde_json::to_string_pretty(&self).expect("Serialization failure")
+            serde_json::to_string_pretty(&self).map_err(|_| std::fmt::Error)?
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn plan(plan: serde_json::Value) -> Out {
+        serde_json::from_value(plan).unwrap()
+    }
+
+    fn volume(id: &str) -> serde_json::Value {
+        json!({"id": id, "name": "", "size": 1})
+    }
+
+    fn sorted(out: &Out) -> Vec<String> {
+        let mut ids = ids(&out.entries()).into_iter().collect::<Vec<_>>();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn merges_plans_of_a_kind_without_duplicates() {
+        let merged = plan(json!({
+            "Volumes": [volume("vol-1"), volume("vol-2")],
+            "inventory": {"total": 10, "survivors": {"app": 3, "db": 2}},
+        }))
+        .merge(plan(json!({
+            "Volumes": [volume("vol-2"), volume("vol-3")],
+            "inventory": {"total": 8, "survivors": {"app": 1, "web": 4}},
+        })));
+
+        assert!(matches!(merged.resources, Resources::Volumes(_)));
+        assert_eq!(sorted(&merged), ["vol-1", "vol-2", "vol-3"]);
+        // The same resources were matched twice, the strictest limits are kept.
+        assert_eq!(merged.inventory.total, 10);
+        assert_eq!(
+            merged.inventory.survivors,
+            BTreeMap::from([
+                ("app".to_owned(), 1),
+                ("db".to_owned(), 2),
+                ("web".to_owned(), 4)
+            ])
+        );
+    }
+
+    #[test]
+    fn merges_plans_of_different_kinds_into_all() {
+        let merged = plan(json!({
+            "Snapshots": [{"id": "snap-1", "name": "", "size": 8}],
+            "inventory": {"total": 5, "retained": {"snap-2": "daily"}},
+        }))
+        .merge(plan(json!({
+            "Volumes": [volume("vol-1")],
+            "inventory": {"total": 3},
+        })));
+
+        assert!(matches!(merged.resources, Resources::All(_)));
+        assert_eq!(sorted(&merged), ["snap-1", "vol-1"]);
+        assert_eq!(merged.inventory.total, 8);
+        assert_eq!(merged.inventory.retained.len(), 1);
+        assert!(merged.digest.is_none());
+    }
+}
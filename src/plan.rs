@@ -0,0 +1,143 @@
+use crate::{
+    error::{regex, Error, Result},
+    options::{PlanCommand, PlanFilter},
+    out::{Entry, Out},
+    status::Status,
+};
+use chrono::{DateTime, Utc};
+use std::{collections::HashSet, path::Path};
+
+/// Criteria top level resources must all match to stay in the plan.
+struct Criteria {
+    ids: Option<HashSet<String>>,
+    regex: Option<regex::Regex>,
+    tags: Vec<(String, String)>,
+    before: Option<DateTime<Utc>>,
+}
+
+impl Criteria {
+    fn new(filter: &PlanFilter) -> Result<Self> {
+        let older = &filter.older;
+        Ok(Self {
+            ids: filter.ids.as_ref().map(|ids| ids.iter().cloned().collect()),
+            regex: filter.regex.as_deref().map(regex).transpose()?,
+            tags: filter
+                .tags
+                .iter()
+                .flatten()
+                .map(|tag| match tag.split_once('=') {
+                    Some((key, value)) => Ok((key.to_owned(), value.to_owned())),
+                    None => Err(Error::InvalidTag(tag.clone())),
+                })
+                .collect::<Result<_>>()?,
+            before: if older.hours != 0 || older.days != 0 || older.weeks != 0 {
+                Some(older.date().ok_or(Error::DateOutOfRange)?)
+            } else {
+                None
+            },
+        })
+    }
+
+    /// Resources of unknown age are never older than the duration.
+    fn matches(&self, entry: &Entry) -> bool {
+        self.ids.as_ref().is_none_or(|ids| ids.contains(&entry.id))
+            && self
+                .regex
+                .as_ref()
+                .is_none_or(|regex| regex.is_match(&entry.name))
+            && self
+                .tags
+                .iter()
+                .all(|(key, value)| entry.tags.get(key) == Some(value))
+            && self.before.is_none_or(|before| {
+                entry
+                    .date
+                    .as_deref()
+                    .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+                    .is_some_and(|date| date < before)
+            })
+    }
+}
+
+fn status(out: &Out) -> Status {
+    if out.is_empty() {
+        Status::Clean
+    } else {
+        Status::Candidates
+    }
+}
+
+/// Writes the edited plans, candidates status when any resource is left to delete.
+pub fn edit(command: PlanCommand) -> Result<Status> {
+    match command {
+        PlanCommand::Filter(filter) => {
+            let criteria = Criteria::new(&filter)?;
+            let mut out = Out::read(filter.path)?;
+            let skipped = out
+                .entries()
+                .into_iter()
+                .filter(|entry| !criteria.matches(entry))
+                .map(|entry| entry.id)
+                .collect();
+            out.decide(&skipped, None);
+            log::info!("Kept {} resources", out.len());
+            out.write(filter.output)?;
+            Ok(status(&out))
+        }
+        PlanCommand::Remove(remove) => {
+            let mut out = Out::read(remove.path)?;
+            out.decide(&remove.ids.into_iter().collect(), None);
+            out.write(remove.output)?;
+            Ok(status(&out))
+        }
+        PlanCommand::Merge(merge) => {
+            let mut merged: Option<Out> = None;
+            for path in merge.paths {
+                let out = Out::read(path)?;
+                merged = Some(match merged {
+                    Some(merged) => merged.merge(out),
+                    None => out,
+                });
+            }
+            let Some(mut out) = merged else {
+                return Ok(Status::Clean);
+            };
+            out.write(merge.output)?;
+            Ok(status(&out))
+        }
+        PlanCommand::Split(split) => {
+            let ids = Out::read(split.path.clone())?
+                .entries()
+                .into_iter()
+                .map(|entry| entry.id)
+                .collect::<Vec<_>>();
+
+            let mut status = Status::Clean;
+            for (index, batch) in ids.chunks(split.batch.max(1)).enumerate() {
+                let batch = batch.iter().collect::<HashSet<_>>();
+                let mut out = Out::read(split.path.clone())?;
+                let skipped = ids
+                    .iter()
+                    .filter(|id| !batch.contains(id))
+                    .cloned()
+                    .collect();
+                out.decide(&skipped, None);
+
+                let path = numbered(&split.path, index + 1);
+                log::info!("Saving {} resources to {path:?}", out.len());
+                out.write(path)?;
+                status = status.max(self::status(&out));
+            }
+            Ok(status)
+        }
+    }
+}
+
+/// Path of a batch, its number inserted before the extension.
+fn numbered(path: &Path, number: usize) -> std::path::PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(match path.extension() {
+        Some(extension) => format!("{stem}.{number}.{}", extension.to_string_lossy()),
+        None => format!("{stem}.{number}"),
+    })
+}
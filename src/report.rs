@@ -0,0 +1,282 @@
+use crate::{
+    options::Kind,
+    out::{Entry, Out},
+};
+use std::collections::BTreeMap;
+
+/// Monthly USD per GiB, us-east-1 list prices, by volume type and snapshot tier.
+///
+/// Snapshots are billed on the data they hold, smaller than their volume size: estimates are an
+/// upper bound. Images cost nothing besides their snapshots.
+pub fn price(kind: Kind, details: &BTreeMap<&'static str, String>) -> f64 {
+    let detail = |column| details.get(column).map(String::as_str);
+    match kind {
+        Kind::Image => 0.0,
+        Kind::Snapshot => match detail("tier") {
+            Some("archive") => 0.0125,
+            _ => 0.05,
+        },
+        Kind::Volume => match detail("type") {
+            Some("gp3") => 0.08,
+            Some("io1" | "io2") => 0.125,
+            Some("st1") => 0.045,
+            Some("sc1") => 0.015,
+            Some("standard") => 0.05,
+            _ => 0.10,
+        },
+    }
+}
+
+/// Estimated monthly cost of the entry and its dependents, in USD, protected dependents excluded.
+fn cost(entry: &Entry) -> f64 {
+    entry.own_size as f64 * price(entry.kind, &entry.details)
+        + entry
+            .children
+            .iter()
+            .filter(|child| !child.protected)
+            .map(cost)
+            .sum::<f64>()
+}
+
+/// Resources, storage and cost the plan frees, protected entries excluded.
+fn totals<'a>(entries: impl IntoIterator<Item = &'a Entry>) -> (usize, i64, f64) {
+    entries
+        .into_iter()
+        .filter(|entry| !entry.protected)
+        .fold((0, 0, 0.0), |(count, size, total), entry| {
+            (count + 1, size + entry.size, total + cost(entry))
+        })
+}
+
+fn title(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Image => "Images",
+        Kind::Snapshot => "Snapshots",
+        Kind::Volume => "Volumes",
+    }
+}
+
+/// Summary, then a collapsible section per resource listing its dependents.
+pub fn markdown(out: &Out) -> String {
+    let entries = out.entries();
+    let (_, size, total) = totals(&entries);
+    let cell = |text: &str| text.replace('|', "\\|");
+
+    let mut markdown = format!(
+        "# Cleanup plan\n\n\
+         | Resources | With dependents | Size (GiB) | Estimated cost (USD/month) |\n\
+         | ---: | ---: | ---: | ---: |\n\
+         | {} | {} | {size} | {total:.2} |\n",
+        out.len(),
+        out.count(),
+    );
+
+    for kind in [Kind::Image, Kind::Snapshot, Kind::Volume] {
+        let entries = entries
+            .iter()
+            .filter(|entry| entry.kind == kind)
+            .collect::<Vec<_>>();
+        if entries.is_empty() {
+            continue;
+        }
+        markdown += &format!("\n## {}\n", title(kind));
+
+        for entry in entries {
+            markdown += &format!(
+                "\n<details>\n<summary><code>{}</code> {}, {}, {} GiB, ${:.2}/month{}</summary>\n\n",
+                entry.id,
+                html(&entry.name),
+                entry.date.as_deref().unwrap_or("-"),
+                entry.size,
+                cost(entry),
+                if entry.protected { ", protected" } else { "" }
+            );
+
+            if entry.children.is_empty() {
+                markdown += "No dependents.\n";
+            } else {
+                markdown += "| Kind | ID | Name | Date | Size (GiB) | USD/month | Reason |\n\
+                             | --- | --- | --- | --- | ---: | ---: | --- |\n";
+                for (depth, child) in descendants(&entry.children, 0) {
+                    markdown += &format!(
+                        "| {}{} | `{}` | {} | {} | {} | {:.2} | {} |\n",
+                        "&nbsp;&nbsp;".repeat(depth),
+                        child.kind,
+                        child.id,
+                        cell(&child.name),
+                        child.date.as_deref().unwrap_or("-"),
+                        child.size,
+                        cost(child),
+                        cell(&child.column("reason"))
+                    );
+                }
+            }
+            markdown += "\n</details>\n";
+        }
+    }
+
+    markdown
+}
+
+/// Dependents, each after its parent, with their depth.
+fn descendants(entries: &[Entry], depth: usize) -> Vec<(usize, &Entry)> {
+    entries
+        .iter()
+        .flat_map(|entry| {
+            std::iter::once((depth, entry)).chain(descendants(&entry.children, depth + 1))
+        })
+        .collect()
+}
+
+pub fn html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A row per resource, along with the resource it is deleted with.
+fn rows(entries: &[Entry], parent: Option<&str>, rows: &mut String) {
+    for entry in entries {
+        *rows += &format!(
+            "<tr{}><td>{}</td><td><code>{}</code></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.2}</td><td>{}</td></tr>\n",
+            if entry.protected { " class=\"protected\"" } else { "" },
+            entry.kind,
+            html(&entry.id),
+            html(&entry.name),
+            entry.date.as_deref().map_or_else(|| "-".to_owned(), html),
+            parent.map_or_else(String::new, html),
+            entry.size,
+            html(&entry.column("reason")),
+            cost(entry),
+            entry.protected,
+        );
+        self::rows(&entry.children, Some(&entry.id), rows);
+    }
+}
+
+/// Standalone page with totals per kind and a table of every resource, sorted by clicking headers.
+pub fn page(out: &Out) -> String {
+    let entries = out.entries();
+    let (_, size, total) = totals(&entries);
+
+    let mut summary = String::new();
+    for kind in [Kind::Image, Kind::Snapshot, Kind::Volume] {
+        let (count, size, cost) = totals(entries.iter().filter(|entry| entry.kind == kind));
+        if count == 0 {
+            continue;
+        }
+        summary += &format!(
+            "<tr><td>{}</td><td>{count}</td><td>{size}</td><td>{cost:.2}</td></tr>\n",
+            title(kind)
+        );
+    }
+    summary += &format!(
+        "<tr><th>Total</th><th>{} with dependents</th><th>{size}</th><th>{total:.2}</th></tr>\n",
+        out.count()
+    );
+
+    let mut rows = String::new();
+    self::rows(&entries, None, &mut rows);
+
+    document(
+        "Cleanup plan",
+        &format!(
+            r#"<table>
+<thead><tr><th>Kind</th><th>Resources</th><th>Size (GiB)</th><th>USD/month</th></tr></thead>
+<tbody>
+{summary}</tbody>
+</table>
+<table class="sortable">
+<thead><tr><th>Kind</th><th>ID</th><th>Name</th><th>Date</th><th>With</th><th>Size (GiB)</th><th>Reason</th><th>USD/month</th><th>Protected</th></tr></thead>
+<tbody>
+{rows}</tbody>
+</table>
+"#
+        ),
+    )
+}
+
+/// Standalone page, tables of the `sortable` class sorted by clicking their headers.
+pub fn document(title: &str, body: &str) -> String {
+    format!(
+        r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; }}
+table {{ border-collapse: collapse; margin-bottom: 2em; }}
+th, td {{ border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; }}
+table.sortable th {{ cursor: pointer; background: #f0f0f0; }}
+tr.protected {{ color: #888; }}
+tr.unused {{ background: #fdf0d0; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p>Estimated costs use us-east-1 list prices per GiB-month and are an upper bound for snapshots.</p>
+{body}<script>
+document.querySelectorAll("table.sortable th").forEach((header) => {{
+  header.addEventListener("click", () => {{
+    const column = header.cellIndex;
+    const body = header.closest("table").tBodies[0];
+    const ascending = header.dataset.order !== "asc";
+    header.dataset.order = ascending ? "asc" : "desc";
+    [...body.rows]
+      .sort((a, b) => {{
+        const [x, y] = [a, b].map((row) => row.cells[column].textContent);
+        const numeric = x !== "" && y !== "" && !isNaN(x) && !isNaN(y);
+        const order = numeric ? x - y : x.localeCompare(y);
+        return ascending ? order : -order;
+      }})
+      .forEach((row) => body.appendChild(row));
+  }});
+}});
+</script>
+</body>
+</html>
+"##
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Entries of the plan, as the image, snapshot and volume plans build them.
+    fn entries(plan: serde_json::Value) -> Vec<Entry> {
+        serde_json::from_value::<Out>(plan).unwrap().entries()
+    }
+
+    fn volume(id: &str, protected: bool) -> serde_json::Value {
+        json!({"id": id, "name": "", "size": 100, "volume_type": "gp2", "protected": protected})
+    }
+
+    #[test]
+    fn protected_dependents_cost_nothing() {
+        let entries = entries(json!({"Images": [{
+            "id": "ami-1",
+            "name": "app-1",
+            "creation_date": "2024-01-01T00:00:00.000Z",
+            "snapshots": [[{
+                "id": "snap-1",
+                "name": "",
+                "size": 8,
+                "volumes": [[volume("vol-1", false), volume("vol-2", true)]],
+            }]],
+        }]}));
+
+        assert!((cost(&entries[0]) - (8.0 * 0.05 + 100.0 * 0.10)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn totals_skip_protected_entries() {
+        let entries = entries(json!({"Volumes": [volume("vol-1", false), volume("vol-2", true)]}));
+        let (count, size, total) = totals(&entries);
+        assert_eq!((count, size), (1, 100));
+        assert!((total - 10.0).abs() < 1e-9);
+    }
+}
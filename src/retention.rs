@@ -0,0 +1,188 @@
+use crate::{
+    error::{Error, Result},
+    options::Gfs,
+};
+use chrono::{DateTime, Datelike, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Grandfather-father-son rule preserving an item, finest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rule {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl Rule {
+    /// Calendar period the date falls in, in the schedule timezone.
+    fn bucket(self, date: DateTime<Tz>) -> (i32, u32) {
+        match self {
+            Self::Daily => (date.year(), date.ordinal()),
+            Self::Weekly => (date.iso_week().year(), date.iso_week().week()),
+            Self::Monthly => (date.year(), date.month()),
+            Self::Yearly => (date.year(), 0),
+        }
+    }
+}
+
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Daily => "daily",
+            Self::Weekly => "weekly",
+            Self::Monthly => "monthly",
+            Self::Yearly => "yearly",
+        })
+    }
+}
+
+/// Number of periods of each rule to keep the newest item of.
+pub struct Schedule {
+    rules: [(Rule, usize); 4],
+    timezone: Tz,
+}
+
+impl Schedule {
+    /// Refuses schedules keeping nothing, which would delete every item.
+    pub fn new(gfs: &Gfs) -> Result<Self> {
+        if gfs.daily + gfs.weekly + gfs.monthly + gfs.yearly == 0 {
+            return Err(Error::EmptySchedule);
+        }
+
+        Ok(Self {
+            rules: [
+                (Rule::Daily, gfs.daily),
+                (Rule::Weekly, gfs.weekly),
+                (Rule::Monthly, gfs.monthly),
+                (Rule::Yearly, gfs.yearly),
+            ],
+            timezone: gfs
+                .timezone
+                .parse()
+                .map_err(|_| Error::InvalidTimezone(gfs.timezone.clone()))?,
+        })
+    }
+
+    /// Finest rule preserving each item, `None` for items to delete.
+    ///
+    /// An item kept by one rule still counts as the newest of its period for the coarser ones.
+    pub fn retain(&self, dates: &[DateTime<Utc>]) -> Vec<Option<Rule>> {
+        let mut newest = (0..dates.len()).collect::<Vec<_>>();
+        newest.sort_by_key(|index| std::cmp::Reverse(dates[*index]));
+
+        let mut rules = vec![None; dates.len()];
+        for (rule, count) in self.rules {
+            let mut buckets = HashSet::new();
+            for index in &newest {
+                if buckets.len() == count {
+                    break;
+                }
+                if buckets.insert(rule.bucket(dates[*index].with_timezone(&self.timezone))) {
+                    rules[*index].get_or_insert(rule);
+                }
+            }
+        }
+
+        rules
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(daily: usize, weekly: usize, monthly: usize, timezone: &str) -> Schedule {
+        Schedule::new(&Gfs {
+            daily,
+            weekly,
+            monthly,
+            yearly: 0,
+            timezone: timezone.to_owned(),
+        })
+        .unwrap()
+    }
+
+    fn dates(dates: &[&str]) -> Vec<DateTime<Utc>> {
+        dates.iter().map(|date| date.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn keeps_the_newest_of_each_period() {
+        let dates = dates(&[
+            "2024-03-04T08:00:00Z",
+            "2024-03-04T10:00:00Z",
+            "2024-03-03T10:00:00Z",
+            "2024-03-01T10:00:00Z",
+            "2024-02-20T10:00:00Z",
+        ]);
+        assert_eq!(
+            schedule(2, 0, 0, "UTC").retain(&dates),
+            [None, Some(Rule::Daily), Some(Rule::Daily), None, None]
+        );
+    }
+
+    #[test]
+    fn finer_rules_count_for_coarser_ones() {
+        // Monday 4th and Sunday 3rd fall in different ISO weeks, February in another month.
+        let dates = dates(&[
+            "2024-03-04T10:00:00Z",
+            "2024-03-03T10:00:00Z",
+            "2024-03-02T10:00:00Z",
+            "2024-02-20T10:00:00Z",
+        ]);
+        assert_eq!(
+            schedule(1, 2, 2, "UTC").retain(&dates),
+            [
+                Some(Rule::Daily),
+                Some(Rule::Weekly),
+                None,
+                Some(Rule::Monthly)
+            ]
+        );
+    }
+
+    #[test]
+    fn periods_follow_the_timezone() {
+        let dates = dates(&["2024-01-01T23:30:00Z", "2024-01-01T22:30:00Z"]);
+        assert_eq!(
+            schedule(2, 0, 0, "UTC").retain(&dates),
+            [Some(Rule::Daily), None]
+        );
+        // The newest is on January 2nd in Paris.
+        assert_eq!(
+            schedule(2, 0, 0, "Europe/Paris").retain(&dates),
+            [Some(Rule::Daily), Some(Rule::Daily)]
+        );
+    }
+
+    #[test]
+    fn rejects_schedules_keeping_nothing() {
+        let gfs = Gfs {
+            daily: 0,
+            weekly: 0,
+            monthly: 0,
+            yearly: 0,
+            timezone: "UTC".to_owned(),
+        };
+        assert!(matches!(Schedule::new(&gfs), Err(Error::EmptySchedule)));
+    }
+
+    #[test]
+    fn rejects_unknown_timezones() {
+        let gfs = Gfs {
+            daily: 1,
+            weekly: 0,
+            monthly: 0,
+            yearly: 0,
+            timezone: "Mars/Olympus".to_owned(),
+        };
+        assert!(matches!(
+            Schedule::new(&gfs),
+            Err(Error::InvalidTimezone(_))
+        ));
+    }
+}
@@ -0,0 +1,118 @@
+use crate::{
+    error::{Error, Result},
+    out::{Entry, Out},
+};
+use std::{
+    collections::HashSet,
+    io::{BufRead, Write},
+};
+
+/// Counts, total size, oldest and newest entries of the plan.
+pub fn summary(out: &Out) -> String {
+    let entries = out
+        .entries()
+        .into_iter()
+        .filter(|entry| !entry.protected)
+        .collect::<Vec<_>>();
+
+    let mut summary = format!(
+        "{} resources to delete, {} with dependents, {} GiB",
+        out.len(),
+        out.count(),
+        out.size()
+    );
+
+    let dated = entries.iter().filter(|entry| entry.date.is_some());
+    if let (Some(oldest), Some(newest)) = (
+        dated.clone().min_by_key(|entry| &entry.date),
+        dated.max_by_key(|entry| &entry.date),
+    ) {
+        summary += &format!("\nOldest: {}", line(oldest));
+        summary += &format!("\nNewest: {}", line(newest));
+    }
+
+    summary
+}
+
+fn line(entry: &Entry) -> String {
+    format!(
+        "{} {:?} {} {} GiB{}",
+        entry.id,
+        entry.name,
+        entry.date.as_deref().unwrap_or("-"),
+        entry.size,
+        if entry.protected { " (protected)" } else { "" }
+    )
+}
+
+fn ask(question: &str) -> Result<String> {
+    let mut stdout = std::io::stdout();
+    write!(stdout, "{question} ").map_err(Error::Prompt)?;
+    stdout.flush().map_err(Error::Prompt)?;
+
+    let mut answer = String::new();
+    let read = std::io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .map_err(Error::Prompt)?;
+    if read == 0 {
+        return Err(Error::Prompt(std::io::ErrorKind::UnexpectedEof.into()));
+    }
+    Ok(answer.trim().to_owned())
+}
+
+/// Shows the summary and asks the operator to confirm.
+pub fn confirm(out: &Out) -> Result<bool> {
+    println!("{}", summary(out));
+    Ok(matches!(
+        ask("Proceed with deletion? [y/N]")?.as_str(),
+        "y" | "Y" | "yes"
+    ))
+}
+
+/// Asks the operator to accept, skip or protect each entry, updating the plan accordingly.
+///
+/// Quitting skips the entries left: nothing gets deleted without an explicit decision.
+pub fn review(out: &mut Out) -> Result<()> {
+    let entries = out.entries();
+    let mut skipped = HashSet::new();
+    // Entries protected by an earlier review stay so.
+    let mut protected = entries
+        .iter()
+        .filter(|entry| entry.protected)
+        .map(|entry| entry.id.clone())
+        .collect::<HashSet<_>>();
+
+    let total = entries.len();
+    let mut entries = entries.into_iter().enumerate();
+
+    while let Some((index, entry)) = entries.next() {
+        println!("[{}/{total}] {}", index + 1, line(&entry));
+        loop {
+            match ask("[a]ccept, [s]kip, [p]rotect, [q]uit?")?.as_str() {
+                "a" => {}
+                "s" => {
+                    skipped.insert(entry.id);
+                }
+                "p" => {
+                    protected.insert(entry.id);
+                }
+                "q" => {
+                    skipped.insert(entry.id);
+                    skipped.extend(entries.by_ref().map(|(_, entry)| entry.id));
+                }
+                _ => continue,
+            }
+            break;
+        }
+    }
+
+    log::info!(
+        "Skipped {} and protected {} of {total} resources",
+        skipped.len(),
+        protected.len()
+    );
+    out.decide(&skipped, Some(&protected));
+
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use crate::{
+    error::{Error, Result},
+    out::{self, Out},
+};
+use ed25519_dalek::{Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+/// Detached signature of a plan digest.
+#[derive(Serialize, Deserialize)]
+struct Signature {
+    /// Public key, hex encoded.
+    key: String,
+    signature: String,
+}
+
+/// Signatures of a plan, one per signer, saved next to it.
+fn path(plan: &Path) -> PathBuf {
+    let mut path = plan.as_os_str().to_owned();
+    path.push(".sig");
+    PathBuf::from(path)
+}
+
+fn read(path: &Path) -> Result<Vec<Signature>> {
+    match std::fs::read(path) {
+        Ok(content) => Ok(serde_json::from_slice(&content)?),
+        Err(source) if source.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(source) => Err(Error::Io {
+            path: path.to_owned(),
+            source,
+        }),
+    }
+}
+
+/// Decodes hex digits into exactly `N` bytes.
+fn decode<const N: usize>(text: &str) -> Option<[u8; N]> {
+    hex::decode(text.trim()).ok()?.try_into().ok()
+}
+
+fn verifying_key(key: &str) -> Result<VerifyingKey> {
+    decode(key)
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+        .ok_or_else(|| Error::InvalidKey(key.to_owned()))
+}
+
+/// Secret key of the file, generated when missing.
+fn signing_key(path: &Path) -> Result<SigningKey> {
+    let io = |source| Error::Io {
+        path: path.to_owned(),
+        source,
+    };
+
+    match std::fs::read_to_string(path) {
+        Ok(key) => decode(&key)
+            .map(|secret| SigningKey::from_bytes(&secret))
+            .ok_or_else(|| Error::InvalidKey(path.display().to_string())),
+        Err(source) if source.kind() == std::io::ErrorKind::NotFound => {
+            let mut secret = [0; 32];
+            getrandom::getrandom(&mut secret).map_err(|error| io(error.into()))?;
+            std::fs::write(path, hex::encode(secret)).map_err(io)?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+                    .map_err(io)?;
+            }
+            log::warn!("Generated a new key in {path:?}");
+            Ok(SigningKey::from_bytes(&secret))
+        }
+        Err(source) => Err(io(source)),
+    }
+}
+
+/// Digest recorded in the plan, checked against its content.
+fn digest(out: &Out, plan: &Path) -> Result<String> {
+    match &out.digest {
+        Some(digest) if *digest == out.content_digest()? => Ok(digest.clone()),
+        _ => Err(Error::InvalidDigest(plan.to_owned())),
+    }
+}
+
+/// Signs the plan digest, replacing any previous signature by the same key.
+///
+/// Returns the public key, to trust when applying.
+pub fn sign(plan: &Path, key: &Path) -> Result<String> {
+    let digest = digest(&Out::read(plan.to_owned())?, plan)?;
+    let key = signing_key(key)?;
+    let public = hex::encode(key.verifying_key().to_bytes());
+
+    let path = path(plan);
+    let mut signatures = read(&path)?;
+    signatures.retain(|signature| signature.key != public);
+    signatures.push(Signature {
+        key: public.clone(),
+        signature: hex::encode(key.sign(digest.as_bytes()).to_bytes()),
+    });
+
+    std::fs::write(&path, serde_json::to_string_pretty(&signatures)?)
+        .map_err(|source| Error::Io { path, source })?;
+    Ok(public)
+}
+
+/// Resources a verified plan deletes, which reviewing it may only narrow.
+pub struct Verified {
+    plan: PathBuf,
+    pending: HashSet<String>,
+}
+
+impl Verified {
+    fn new(out: &Out, plan: &Path) -> Self {
+        Self {
+            plan: plan.to_owned(),
+            pending: out::pending(&out.entries()),
+        }
+    }
+
+    /// Checks the reviewed plan only skips or protects resources of the verified one, never
+    /// unprotecting nor adding any.
+    pub fn check(&self, out: &Out) -> Result<()> {
+        if out::pending(&out.entries()).is_subset(&self.pending) {
+            Ok(())
+        } else {
+            Err(Error::Widened(self.plan.clone()))
+        }
+    }
+}
+
+/// Checks the plan is unchanged since written, and signed by one of the trusted keys if any.
+///
+/// Plans written before digests were recorded are only accepted when explicitly allowed, and no
+/// signature is required.
+pub fn verify(
+    out: &Out,
+    plan: &Path,
+    trusted: &[String],
+    allow_unsigned: bool,
+) -> Result<Verified> {
+    if out.digest.is_none() && trusted.is_empty() && allow_unsigned {
+        log::warn!("{plan:?} has no digest, its integrity cannot be checked");
+        return Ok(Verified::new(out, plan));
+    }
+    let digest = digest(out, plan)?;
+
+    if trusted.is_empty() {
+        return Ok(Verified::new(out, plan));
+    }
+    let trusted = trusted
+        .iter()
+        .map(|key| verifying_key(key))
+        .collect::<Result<Vec<_>>>()?;
+
+    let signed = read(&path(plan))?.iter().any(|signature| {
+        let Some(bytes) = decode(&signature.signature) else {
+            return false;
+        };
+        trusted.iter().any(|key| {
+            hex::encode(key.to_bytes()) == signature.key
+                && key
+                    .verify(
+                        digest.as_bytes(),
+                        &ed25519_dalek::Signature::from_bytes(&bytes),
+                    )
+                    .is_ok()
+        })
+    });
+
+    if signed {
+        Ok(Verified::new(out, plan))
+    } else {
+        Err(Error::Unsigned(plan.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn plan(volumes: serde_json::Value) -> Out {
+        serde_json::from_value(json!({ "Volumes": volumes })).unwrap()
+    }
+
+    #[test]
+    fn reviews_may_only_narrow_the_verified_plan() {
+        let volumes = json!([
+            {"id": "vol-1", "name": "", "size": 1},
+            {"id": "vol-2", "name": "", "size": 1, "protected": true},
+        ]);
+        let verified = Verified::new(&plan(volumes.clone()), Path::new("plan.json"));
+
+        let mut reviewed = plan(volumes.clone());
+        reviewed.decide(&HashSet::new(), Some(&HashSet::from(["vol-1".to_owned()])));
+        assert!(verified.check(&reviewed).is_err());
+
+        let mut reviewed = plan(volumes.clone());
+        reviewed.decide(&HashSet::from(["vol-1".to_owned()]), None);
+        assert!(verified.check(&reviewed).is_ok());
+
+        let mut reviewed = plan(volumes);
+        reviewed.decide(&HashSet::new(), Some(&HashSet::new()));
+        assert!(matches!(verified.check(&reviewed), Err(Error::Widened(_))));
+    }
+}
@@ -0,0 +1,70 @@
+use std::{cmp::Ordering, process::ExitCode};
+
+/// Outcome of a run, exposed as the process exit code.
+///
+/// Statuses compare by severity, not by exit code: the most severe one wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Nothing to clean.
+    Clean = 0,
+
+    /// Candidates found, and deleted when applying.
+    Candidates = 3,
+
+    /// Some deletions failed.
+    PartialFailure = 4,
+
+    /// Unexpected failure, e.g. a throttled or unreachable API.
+    Failure = 1,
+
+    /// Invalid options, filters or plan file.
+    InvalidInput = 2,
+
+    /// Missing or expired credentials, or missing permissions.
+    Unauthorized = 5,
+}
+
+impl Status {
+    fn severity(self) -> u8 {
+        match self {
+            Self::Clean => 0,
+            Self::Candidates => 1,
+            Self::PartialFailure => 2,
+            Self::Failure => 3,
+            Self::InvalidInput => 4,
+            Self::Unauthorized => 5,
+        }
+    }
+}
+
+impl Ord for Status {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.severity().cmp(&other.severity())
+    }
+}
+
+impl PartialOrd for Status {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl From<Status> for ExitCode {
+    fn from(status: Status) -> Self {
+        ExitCode::from(status as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Status;
+
+    #[test]
+    fn errors_outrank_candidates() {
+        assert!(Status::Failure > Status::Candidates);
+        assert!(Status::Failure > Status::PartialFailure);
+        assert!(Status::InvalidInput > Status::PartialFailure);
+        assert_eq!(Status::Candidates.max(Status::Failure), Status::Failure);
+        assert_eq!(Status::Clean.max(Status::Candidates), Status::Candidates);
+    }
+}
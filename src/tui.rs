@@ -0,0 +1,388 @@
+use crate::{
+    error::{Error, Result},
+    out::{Entry, Out},
+};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, List, ListItem, ListState, Paragraph},
+    DefaultTerminal, Frame,
+};
+use regex::Regex;
+use std::collections::HashSet;
+
+/// What the operator asked for when leaving.
+pub enum Outcome {
+    Quit,
+    Save,
+    Apply,
+}
+
+#[derive(Clone, Copy)]
+enum Sort {
+    Age,
+    Size,
+    Name,
+}
+
+impl Sort {
+    fn next(self) -> Self {
+        match self {
+            Self::Age => Self::Size,
+            Self::Size => Self::Name,
+            Self::Name => Self::Age,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Age => "age",
+            Self::Size => "size",
+            Self::Name => "name",
+        }
+    }
+}
+
+enum Mode {
+    Browse,
+    Filter(String),
+    Confirm,
+}
+
+struct Row<'a> {
+    entry: &'a Entry,
+    depth: usize,
+    root: &'a str,
+}
+
+struct App {
+    entries: Vec<Entry>,
+    expanded: HashSet<String>,
+    skipped: HashSet<String>,
+    protected: HashSet<String>,
+    sort: Sort,
+    filter: Option<Regex>,
+    tags: bool,
+    mode: Mode,
+    state: ListState,
+    message: String,
+    can_save: bool,
+}
+
+const HELP: &str = "↑↓ move  →← expand  space select  p protect  s sort  / filter  t tags  w save  a apply  q quit";
+
+impl App {
+    fn new(out: &Out, can_save: bool) -> Self {
+        let entries = out.entries();
+        let protected = entries
+            .iter()
+            .filter(|entry| entry.protected)
+            .map(|entry| entry.id.clone())
+            .collect();
+
+        let mut app = Self {
+            entries,
+            expanded: HashSet::new(),
+            skipped: HashSet::new(),
+            protected,
+            sort: Sort::Age,
+            filter: None,
+            tags: false,
+            mode: Mode::Browse,
+            state: ListState::default(),
+            message: HELP.to_owned(),
+            can_save,
+        };
+        app.sort();
+        app.state.select((!app.entries.is_empty()).then_some(0));
+        app
+    }
+
+    fn sort(&mut self) {
+        match self.sort {
+            Sort::Age => self.entries.sort_by(|lhs, rhs| lhs.date.cmp(&rhs.date)),
+            Sort::Size => self
+                .entries
+                .sort_by_key(|entry| std::cmp::Reverse(entry.size)),
+            Sort::Name => self.entries.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name)),
+        }
+    }
+
+    /// Visible rows: filtered and sorted top level entries, with their expanded dependents.
+    fn rows(&self) -> Vec<Row<'_>> {
+        fn push<'a>(
+            rows: &mut Vec<Row<'a>>,
+            expanded: &HashSet<String>,
+            entry: &'a Entry,
+            depth: usize,
+            root: &'a str,
+        ) {
+            rows.push(Row { entry, depth, root });
+            if expanded.contains(&entry.id) {
+                for child in &entry.children {
+                    push(rows, expanded, child, depth + 1, root);
+                }
+            }
+        }
+
+        let mut rows = vec![];
+        for entry in &self.entries {
+            let visible = self
+                .filter
+                .as_ref()
+                .is_none_or(|filter| filter.is_match(&entry.id) || filter.is_match(&entry.name));
+            if visible {
+                push(&mut rows, &self.expanded, entry, 0, &entry.id);
+            }
+        }
+        rows
+    }
+
+    fn selected(&self) -> Option<(String, String, bool)> {
+        let rows = self.rows();
+        let row = rows.get(self.state.selected()?)?;
+        Some((
+            row.entry.id.clone(),
+            row.root.to_owned(),
+            !row.entry.children.is_empty(),
+        ))
+    }
+
+    /// Top level entries to delete: neither skipped nor protected.
+    fn pending(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().filter(|entry| {
+            !self.skipped.contains(&entry.id) && !self.protected.contains(&entry.id)
+        })
+    }
+
+    fn toggle(set: &mut HashSet<String>, id: String) {
+        if !set.remove(&id) {
+            set.insert(id);
+        }
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        let len = self.rows().len();
+        if len == 0 {
+            self.state.select(None);
+        } else {
+            let current = self.state.selected().unwrap_or(0) as isize;
+            self.state
+                .select(Some((current + delta).clamp(0, len as isize - 1) as usize));
+        }
+    }
+
+    /// Handles a key press, returning the outcome when leaving.
+    fn key(&mut self, code: KeyCode) -> Option<Outcome> {
+        match &mut self.mode {
+            Mode::Filter(input) => {
+                match code {
+                    KeyCode::Enter => {
+                        let pattern = std::mem::take(input);
+                        self.mode = Mode::Browse;
+                        if pattern.is_empty() {
+                            self.filter = None;
+                            self.message = HELP.to_owned();
+                        } else {
+                            match Regex::new(&pattern) {
+                                Ok(regex) => {
+                                    self.filter = Some(regex);
+                                    self.message = format!("Filtered by {pattern:?}");
+                                }
+                                Err(error) => self.message = format!("Invalid regex: {error}"),
+                            }
+                        }
+                        self.state.select(Some(0));
+                        self.move_by(0);
+                    }
+                    KeyCode::Esc => self.mode = Mode::Browse,
+                    KeyCode::Backspace => {
+                        input.pop();
+                    }
+                    KeyCode::Char(c) => input.push(c),
+                    _ => {}
+                }
+                None
+            }
+            Mode::Confirm => {
+                self.mode = Mode::Browse;
+                if code == KeyCode::Char('y') {
+                    Some(Outcome::Apply)
+                } else {
+                    self.message = "Apply cancelled".to_owned();
+                    None
+                }
+            }
+            Mode::Browse => {
+                match code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Some(Outcome::Quit),
+                    KeyCode::Up | KeyCode::Char('k') => self.move_by(-1),
+                    KeyCode::Down | KeyCode::Char('j') => self.move_by(1),
+                    KeyCode::PageUp => self.move_by(-10),
+                    KeyCode::PageDown => self.move_by(10),
+                    KeyCode::Right | KeyCode::Char('l') | KeyCode::Enter => {
+                        if let Some((id, _, true)) = self.selected() {
+                            self.expanded.insert(id);
+                        }
+                    }
+                    KeyCode::Left | KeyCode::Char('h') => {
+                        if let Some((id, root, _)) = self.selected() {
+                            if !self.expanded.remove(&id) && self.expanded.remove(&root) {
+                                let position =
+                                    self.rows().iter().position(|row| row.entry.id == root);
+                                self.state.select(position);
+                            }
+                        }
+                    }
+                    KeyCode::Char(' ') => {
+                        if let Some((_, root, _)) = self.selected() {
+                            Self::toggle(&mut self.skipped, root);
+                        }
+                    }
+                    KeyCode::Char('p') => {
+                        if let Some((_, root, _)) = self.selected() {
+                            Self::toggle(&mut self.protected, root);
+                        }
+                    }
+                    KeyCode::Char('s') => {
+                        self.sort = self.sort.next();
+                        self.sort();
+                        self.message = format!("Sorted by {}", self.sort.label());
+                    }
+                    KeyCode::Char('/') => self.mode = Mode::Filter(String::new()),
+                    KeyCode::Char('t') => self.tags = !self.tags,
+                    KeyCode::Char('w') => {
+                        if self.can_save {
+                            return Some(Outcome::Save);
+                        }
+                        self.message = "No plan file to save to, use -o/--output".to_owned();
+                    }
+                    KeyCode::Char('a') => {
+                        let (count, size) = self.pending().fold((0, 0), |(count, size), entry| {
+                            (count + 1, size + entry.size)
+                        });
+                        self.message =
+                            format!("Delete {count} resources, {size} GiB? Press y to confirm");
+                        self.mode = Mode::Confirm;
+                    }
+                    _ => {}
+                }
+                None
+            }
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [list, tags] = Layout::horizontal(if self.tags {
+            [Constraint::Percentage(65), Constraint::Percentage(35)]
+        } else {
+            [Constraint::Percentage(100), Constraint::Length(0)]
+        })
+        .areas(main);
+
+        let rows = self.rows();
+        let items = rows
+            .iter()
+            .map(|row| {
+                let mark = if self.protected.contains(row.root) {
+                    "[P]"
+                } else if self.skipped.contains(row.root) {
+                    "[ ]"
+                } else {
+                    "[x]"
+                };
+                let arrow = if row.entry.children.is_empty() {
+                    " "
+                } else if self.expanded.contains(&row.entry.id) {
+                    "▾"
+                } else {
+                    "▸"
+                };
+                ListItem::new(format!(
+                    "{mark} {}{arrow} {:<8} {:<24} {:<40} {:<24} {:>6} GiB",
+                    "  ".repeat(row.depth),
+                    row.entry.kind.to_string(),
+                    row.entry.id,
+                    row.entry.name,
+                    row.entry.date.as_deref().unwrap_or("-"),
+                    row.entry.size,
+                ))
+            })
+            .collect::<Vec<_>>();
+
+        let lines = self
+            .state
+            .selected()
+            .and_then(|index| rows.get(index))
+            .map(|row| {
+                row.entry
+                    .tags
+                    .iter()
+                    .map(|(key, value)| Line::from(format!("{key} = {value}")))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        let (count, size) = self.pending().fold((0, 0), |(count, size), entry| {
+            (count + 1, size + entry.size)
+        });
+        let title = format!(
+            " {count}/{} selected, {size} GiB, sorted by {} ",
+            self.entries.len(),
+            self.sort.label()
+        );
+
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title(title))
+                .highlight_style(Style::new().add_modifier(Modifier::REVERSED)),
+            list,
+            &mut self.state,
+        );
+
+        if self.tags {
+            frame.render_widget(
+                Paragraph::new(lines).block(Block::bordered().title(" Tags ")),
+                tags,
+            );
+        }
+
+        let status_line = match &self.mode {
+            Mode::Filter(input) => format!("/{input}"),
+            _ => self.message.clone(),
+        };
+        frame.render_widget(Paragraph::new(status_line), status);
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> std::io::Result<Outcome> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    if let Some(outcome) = self.key(key.code) {
+                        return Ok(outcome);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Lets the operator curate the plan, recording skipped and protected entries in it.
+pub fn curate(out: &mut Out, can_save: bool) -> Result<Outcome> {
+    let mut app = App::new(out, can_save);
+
+    let mut terminal = ratatui::init();
+    let outcome = app.run(&mut terminal);
+    ratatui::restore();
+
+    let outcome = outcome.map_err(Error::Terminal)?;
+    if !matches!(outcome, Outcome::Quit) {
+        out.decide(&app.skipped, Some(&app.protected));
+    }
+    Ok(outcome)
+}